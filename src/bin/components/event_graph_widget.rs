@@ -1,268 +1,645 @@
-use std::borrow::BorrowMut;
 use std::ops::Deref;
-use std::rc::Rc;
 
-use web_sys::console::log;
 use yew::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::HtmlSelectElement;
 use gloo::console::log;
-use delays::DelayGraph;
+use delays::{Errors, Quantity, Term, TimebaseEventKey};
+use delays::project::{Labels, Project};
 
-use super::number_input::{NumberInput, ValueTypes};
+use super::export_buttons::export_buttons;
+use super::file_import::FileImport;
+use super::graph_view::{connected_to, graph_view, is_connected};
+use super::number_input::NumberInput;
+use super::projects::{self, ProjectBar};
 use super::text_input::TextInput;
+use super::timeline::{lanes, Timeline};
 
 #[derive(Clone)]
 struct DelayGraphData {
-    events: Vec<String>,
-    timebases: Vec<String>,
+    /// name the project is saved under
+    name: String,
+    project: Project,
+    /// (event id, timebase id) of the clicked cell
     clicked_time: Option<(usize, usize)>,
-    control_clicked_time: Option<(usize, usize)>,
-    event_graph: DelayGraph,
+    /// Delay picked in the graph, highlighted in the delay table
+    selected_delay: Option<Quantity>,
+    /// Ends chosen for the delay being added
+    new_delay: Option<Quantity>,
+    /// Rows of the last import that could not be entered
+    import_errors: Vec<String>,
+    /// The last time or delay typed in that could not be entered
+    rejected: Option<Rejection>,
+}
+
+#[derive(Clone)]
+struct Rejection {
+    quantity: Quantity,
+    value: Option<f64>,
+    message: String,
+    /// Entries that could be removed to make room for `value`
+    conflicts: Vec<Quantity>,
 }
 
 impl DelayGraphData {
-    pub fn new(n_events: usize, n_timebases: usize) -> Self {
-        let events: Vec<String> = (0..n_events)
-        .map(|i| format!("event {}", i).to_owned())
-        .collect();
-        let timebases: Vec<String> = (0..n_timebases)
-        .map(|i| format!("timebase {}", i).to_owned())
-        .collect();
-        let mut event_graph = DelayGraph::new();
-        DelayGraphData { events, timebases, clicked_time: None, control_clicked_time: None, event_graph }
+    pub fn new(name: String, project: Project) -> Self {
+        DelayGraphData { name, project, clicked_time: None, selected_delay: None, new_delay: None, import_errors: Vec::new(), rejected: None }
+    }
+
+    /// The project from the page's link, else the one open last time, else an empty one.
+    fn restore() -> Self {
+        if let Some(project) = projects::shared_project() {
+            return Self::new(projects::unused_name(), project)
+        }
+        let name = projects::current_name().unwrap_or_else(projects::unused_name);
+        let project = projects::load(&name).unwrap_or_else(empty_project);
+        Self::new(name, project)
+    }
+
+    /// Forget clicked cells and chosen delays whose event or timebase no longer exists.
+    fn clear_removed_clicks(&mut self) {
+        let exists = |(e, t): (usize, usize), project: &Project| {
+            project.events.name(e).is_some() && project.timebases.name(t).is_some()
+        };
+        let ends_exist = |quantity: Quantity, project: &Project| {
+            [quantity.from, quantity.to].into_iter().all(|key| match key.event {
+                delays::Event::Event(event) => exists((event, key.timebase), project),
+                delays::Event::T0 => false,
+            })
+        };
+        if self.clicked_time.is_some_and(|cell| !exists(cell, &self.project)) {
+            self.clicked_time = None;
+        }
+        if self.selected_delay.is_some_and(|quantity| !ends_exist(quantity, &self.project)) {
+            self.selected_delay = None;
+        }
+        if self.new_delay.is_some_and(|quantity| !ends_exist(quantity, &self.project)) {
+            self.new_delay = None;
+        }
+    }
+}
+
+fn empty_project() -> Project {
+    Project::with_size(6, 6)
+}
+
+/// First unused name of the form "`prefix` n".
+fn unused_name(labels: &Labels, prefix: &str) -> String {
+    (labels.len()..).map(|n| format!("{} {}", prefix, n)).find(|name| labels.id(name).is_none()).unwrap()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Axis {
+    Timebase,
+    Event,
+}
+
+#[derive(Clone, PartialEq)]
+enum Edit {
+    Rename(String),
+    Earlier,
+    Later,
+    Remove,
+}
+
+/// Apply `edit` to the timebase or event with `id`.
+fn edit_label(state: &UseStateHandle<DelayGraphData>, axis: Axis, id: usize, edit: Edit) {
+    let mut data = state.deref().clone();
+    let project = &mut data.project;
+    let labels = match axis {
+        Axis::Timebase => &mut project.timebases,
+        Axis::Event => &mut project.events,
+    };
+    let position = labels.position(id).unwrap();
+    let removing = edit == Edit::Remove;
+    let result = match edit {
+        Edit::Rename(name) => labels.rename(id, &name),
+        Edit::Earlier => labels.move_to(id, position.saturating_sub(1)),
+        Edit::Later => labels.move_to(id, position + 1),
+        Edit::Remove => match axis {
+            Axis::Timebase => project.remove_timebase(id),
+            Axis::Event => project.remove_event(id),
+        },
+    };
+    if let Err(error) = result {
+        log!(format!("Could not edit the name: {:?}", error));
+    }
+    data.clear_removed_clicks();
+    if removing {
+        data.rejected = None;
+    }
+    state.set(data)
+}
+
+fn rename_callback(state: &UseStateHandle<DelayGraphData>, axis: Axis, id: usize) -> Callback<String> {
+    let cloned_state = state.clone();
+    Callback::from(move |name: String| edit_label(&cloned_state, axis, id, Edit::Rename(name)))
+}
+
+/// Buttons moving or removing one timebase or event.
+fn label_controls(state: &UseStateHandle<DelayGraphData>, axis: Axis, id: usize) -> Html {
+    let (earlier, later) = match axis {
+        Axis::Timebase => ("↑", "↓"),
+        Axis::Event => ("←", "→"),
+    };
+    let button = |edit: Edit| {
+        let cloned_state = state.clone();
+        Callback::from(move |_: MouseEvent| edit_label(&cloned_state, axis, id, edit.clone()))
+    };
+    let (on_earlier, on_later, on_remove) = (button(Edit::Earlier), button(Edit::Later), button(Edit::Remove));
+    html! {
+        <>
+        <button onclick={on_earlier}>{earlier}</button>
+        <button onclick={on_later}>{later}</button>
+        <button onclick={on_remove}>{"✕"}</button>
+        </>
+    }
+}
+
+/// Record a click on the time of event `i` on timebase `j`.
+fn click_callback(state: &UseStateHandle<DelayGraphData>, i: usize, j: usize) -> Callback<MouseEvent> {
+    let cloned_state = state.clone();
+    Callback::from(move |_: MouseEvent| {
+        cloned_state.set(
+            DelayGraphData {
+                clicked_time: Some((i, j)),
+                ..cloned_state.deref().clone()
+            }
+        )
+    })
+}
+
+fn key_name(project: &Project, key: TimebaseEventKey) -> String {
+    let timebase = project.timebases.name(key.timebase).unwrap_or_default();
+    match key.event {
+        delays::Event::Event(event) => format!("{} on {}", project.events.name(event).unwrap_or_default(), timebase),
+        delays::Event::T0 => format!("T0 of {}", timebase),
+    }
+}
+
+/// The entries summed to derive the time of event `i` on timebase `j`, if it is derived rather than entered.
+fn derivation(project: &Project, (i, j): (usize, usize)) -> Option<Vec<Term>> {
+    if project.graph.lookup_time(j, i).is_some() {
+        return None
+    }
+    project.graph.explain(&Quantity::time(j, i))
+}
+
+/// What `quantity` is, in words, e.g. "the time of event 1 on timebase 0".
+fn entry_name(project: &Project, quantity: &Quantity) -> String {
+    let Quantity {from, to} = *quantity;
+    match (from.event, to.event) {
+        (delays::Event::T0, _) => format!("the time of {}", key_name(project, to)),
+        (_, delays::Event::T0) => format!("the time of {}", key_name(project, from)),
+        _ => format!("the delay from {} to {}", key_name(project, from), key_name(project, to)),
+    }
+}
+
+/// Enter, change or with `None` clear the time or delay `quantity`, keeping the reason if that is not possible.
+fn enter(data: &mut DelayGraphData, quantity: Quantity, value: Option<f64>) {
+    let graph = &mut data.project.graph;
+    let Quantity {from, to} = quantity;
+    if from == to {
+        data.rejected = Some(Rejection {quantity, value, message: "A delay needs two different ends".to_owned(), conflicts: Vec::new()});
+        return
+    }
+    let result = match (from.event, to.event, value) {
+        (delays::Event::T0, delays::Event::Event(event), Some(time)) => graph.update_time(to.timebase, event, time),
+        // a time followed back from the event to T0
+        (delays::Event::Event(event), delays::Event::T0, Some(time)) if from.timebase == to.timebase => graph.update_time(from.timebase, event, -time),
+        (delays::Event::Event(event_1), delays::Event::Event(event_2), Some(delay)) => graph.update_delay(from.timebase, event_1, to.timebase, event_2, delay),
+        (_, _, None) => match graph.remove_entry(&quantity) {
+            // clearing a box that was already empty
+            Err(Errors::NotFound) => Ok(()),
+            result => result,
+        },
+        _ => Err(Errors::Unsupported),
+    };
+    data.rejected = match result {
+        Ok(()) => None,
+        Err(Errors::AlreadyConstrained(terms)) => {
+            let total: f64 = terms.iter().map(|term| term.value).sum();
+            let mut path: Vec<String> = terms.iter().map(|term| key_name(&data.project, term.quantity.from)).collect();
+            path.extend(terms.last().map(|term| key_name(&data.project, term.quantity.to)));
+            let message = format!("{} not entered: already derived as {} via {}", value.unwrap_or_default(), total, path.join(" → "));
+            Some(Rejection {quantity, value, message, conflicts: terms.into_iter().map(|term| term.quantity).collect()})
+        },
+        Err(error) => Some(Rejection {quantity, value, message: format!("Not entered: {}", error), conflicts: Vec::new()}),
+    };
+}
+
+fn enter_callback(state: &UseStateHandle<DelayGraphData>, quantity: Quantity) -> Callback<Option<f64>> {
+    let cloned_state = state.clone();
+    Callback::from(move |value| {
+        let mut data = cloned_state.deref().clone();
+        enter(&mut data, quantity, value);
+        cloned_state.set(data)
+    })
+}
+
+/// The reason a time or delay picked by `shown` was not entered, with buttons to remove a conflicting entry and enter it after all.
+fn rejection_html(state: &UseStateHandle<DelayGraphData>, shown: impl Fn(&Quantity) -> bool) -> Html {
+    let Some(rejection) = state.rejected.as_ref().filter(|rejection| shown(&rejection.quantity)) else {
+        return html!()
+    };
+    let buttons = rejection.conflicts.iter().map(|conflict| {
+        let cloned_state = state.clone();
+        let (conflict, quantity, value) = (*conflict, rejection.quantity, rejection.value);
+        let onclick = Callback::from(move |_: MouseEvent| {
+            let mut data = cloned_state.deref().clone();
+            if let Err(error) = data.project.graph.remove_entry(&conflict) {
+                log!(format!("Could not remove the entry: {}", error));
+                return
+            }
+            enter(&mut data, quantity, value);
+            cloned_state.set(data)
+        });
+        html!(<li><button onclick={onclick}>{format!("Replace {}", entry_name(&state.project, &conflict))}</button></li>)
+    }).collect::<Html>();
+    let cloned_state = state.clone();
+    let on_dismiss = Callback::from(move |_: MouseEvent| cloned_state.set(DelayGraphData {rejected: None, ..cloned_state.deref().clone()}));
+    html! {
+        <div style="color: darkred; max-width: 20em;">
+            {&rejection.message}
+            <button onclick={on_dismiss}>{"✕"}</button>
+            <ul>{buttons}</ul>
+        </div>
+    }
+}
+
+/// Dropdown of the names in `labels`, emitting the id picked.
+fn label_select(labels: &Labels, selected: usize, onchange: Callback<usize>) -> Html {
+    let options = labels.iter().map(|label| {
+        html!(<option value={label.id.to_string()} selected={label.id == selected}>{&label.name}</option>)
+    }).collect::<Html>();
+    let on_change = Callback::from(move |event: Event| {
+        let select = event.target().unwrap().unchecked_into::<HtmlSelectElement>();
+        if let Ok(id) = select.value().parse() {
+            onchange.emit(id)
+        }
+    });
+    html!(<select onchange={on_change}>{options}</select>)
+}
+
+/// Timebase and event dropdowns for one end of a delay.
+fn key_selects(project: &Project, key: TimebaseEventKey, onchange: Callback<TimebaseEventKey>) -> Html {
+    let delays::Event::Event(event) = key.event else {
+        return html!()
+    };
+    let timebase = key.timebase;
+    let on_timebase = onchange.reform(move |timebase| TimebaseEventKey::new(timebase, event));
+    let on_event = onchange.reform(move |event| TimebaseEventKey::new(timebase, event));
+    html!(<>{label_select(&project.timebases, timebase, on_timebase)}{label_select(&project.events, event, on_event)}</>)
+}
+
+/// Callback choosing a new `from` end, or `to` end, for `quantity` and handing on the result.
+fn end_callback(quantity: Quantity, from_end: bool, onchange: impl Fn(Quantity) + 'static) -> Callback<TimebaseEventKey> {
+    Callback::from(move |key| match from_end {
+        true => onchange(Quantity {from: key, ..quantity}),
+        false => onchange(Quantity {to: key, ..quantity}),
+    })
+}
+
+/// Move the delay entered as `from` to run between the ends of `to`, leaving it be if `to` can't be entered.
+fn move_delay(data: &mut DelayGraphData, from: Quantity, to: Quantity, delay: f64) {
+    let mut moved = data.clone();
+    enter(&mut moved, from, None);
+    enter(&mut moved, to, Some(delay));
+    match moved.rejected {
+        None => *data = moved,
+        rejected => data.rejected = rejected,
+    }
+}
+
+/// Row editing the entered delay `quantity`.
+fn delay_row(state: &UseStateHandle<DelayGraphData>, quantity: Quantity, delay: f64) -> Html {
+    let move_to = |from_end| {
+        let cloned_state = state.clone();
+        end_callback(quantity, from_end, move |moved| {
+            let mut data = cloned_state.deref().clone();
+            move_delay(&mut data, quantity, moved, delay);
+            cloned_state.set(data)
+        })
+    };
+    let cloned_state = state.clone();
+    let on_remove = Callback::from(move |_: MouseEvent| {
+        let mut data = cloned_state.deref().clone();
+        enter(&mut data, quantity, None);
+        cloned_state.set(data)
+    });
+    let style = if state.selected_delay == Some(quantity) { "background: lightyellow;" } else { "" };
+    html! {
+        <tr key={format!("{} {}", quantity.from, quantity.to)} style={style}>
+            <td>{key_selects(&state.project, quantity.from, move_to(true))}</td>
+            <td>{"→"}</td>
+            <td>{key_selects(&state.project, quantity.to, move_to(false))}</td>
+            <td><NumberInput value={Some(delay)} editable={true} neighbors={0} is_connected={false} onchange={enter_callback(state, quantity)} onclick={Callback::from(|_| ())} /></td>
+            <td><button onclick={on_remove}>{"✕"}</button></td>
+        </tr>
+    }
+}
+
+/// Row choosing the ends of a new delay, which is entered once it is given a value.
+fn new_delay_row(state: &UseStateHandle<DelayGraphData>) -> Html {
+    let project = &state.project;
+    let (Some(first_timebase), Some(first_event)) = (project.timebases.iter().next(), project.events.iter().next()) else {
+        return html!()
+    };
+    let last_timebase = project.timebases.iter().last().unwrap();
+    let quantity = state.new_delay.unwrap_or(Quantity::delay(first_timebase.id, first_event.id, last_timebase.id, first_event.id));
+    let choose = |from_end| {
+        let cloned_state = state.clone();
+        end_callback(quantity, from_end, move |chosen| {
+            cloned_state.set(DelayGraphData {new_delay: Some(chosen), ..cloned_state.deref().clone()})
+        })
+    };
+    let cloned_state = state.clone();
+    let on_value = Callback::from(move |value: Option<f64>| {
+        if value.is_none() {
+            return
+        }
+        let mut data = cloned_state.deref().clone();
+        enter(&mut data, quantity, value);
+        if data.rejected.is_none() {
+            data.new_delay = None;
+        }
+        cloned_state.set(data)
+    });
+    html! {
+        <tr>
+            <td>{key_selects(project, quantity.from, choose(true))}</td>
+            <td>{"→"}</td>
+            <td>{key_selects(project, quantity.to, choose(false))}</td>
+            // keyed by the number of delays, to empty it once the new one is entered
+            <td><NumberInput key={project.graph.delays().len()} value={None} editable={true} neighbors={0} is_connected={false} onchange={on_value} onclick={Callback::from(|_| ())} /></td>
+            <td>{"new"}</td>
+        </tr>
+    }
+}
+
+/// Every entered delay, editable, and the delays between the same event on different timebases derived from them.
+fn delay_table(state: &UseStateHandle<DelayGraphData>) -> Html {
+    let project = &state.project;
+    let rows_html = project.graph.delays().into_iter().map(|(timebase_1, event_1, timebase_2, event_2, delay)| {
+        delay_row(state, Quantity::delay(timebase_1, event_1, timebase_2, event_2), delay)
+    }).collect::<Html>();
+
+    // the same event on each pair of timebases
+    let mut pairs = Vec::new();
+    for event in project.events.iter() {
+        for (n, timebase_1) in project.timebases.iter().enumerate() {
+            for timebase_2 in project.timebases.iter().skip(n + 1) {
+                pairs.push((timebase_1.id, timebase_2.id, event.id));
+            }
+        }
+    }
+    let derived_html = pairs.into_iter()
+        .filter(|&(timebase_1, timebase_2, event)| project.graph.lookup_delay(timebase_1, event, timebase_2, event).is_none())
+        .filter_map(|(timebase_1, timebase_2, event)| {
+            let quantity = Quantity::delay(timebase_1, event, timebase_2, event);
+            let delay = quantity.evaluate(&project.graph)?;
+            Some(html!(<li>{format!("{} → {}: {}", key_name(project, quantity.from), key_name(project, quantity.to), delay)}</li>))
+        }).collect::<Html>();
+
+    html! {
+        <>
+        <table>
+            <tr><th>{"From"}</th><th></th><th>{"To"}</th><th>{"Delay"}</th><th></th></tr>
+            {rows_html}
+            {new_delay_row(state)}
+        </table>
+        {rejection_html(state, |quantity| !quantity.is_time())}
+        <details>
+            <summary>{"Derived delays"}</summary>
+            <ul>{derived_html}</ul>
+        </details>
+        </>
     }
 }
 
 #[function_component(DelayGraphWidget)]
 pub fn event_graph_widget() -> Html {
 
-    let event_graph_data = DelayGraphData::new(6, 6);
-    let state = use_state(|| event_graph_data);
+    let state = use_state(DelayGraphData::restore);
+
+    // save after every change
+    {
+        let state = state.clone();
+        use_effect(move || projects::save(&state.name, &state.project));
+    }
+
+    let project_html = {
+        let cloned_state = state.clone();
+        let on_select = Callback::from(move |name: String| {
+            if let Some(project) = projects::load(&name) {
+                cloned_state.set(DelayGraphData::new(name, project))
+            }
+        });
+        let cloned_state = state.clone();
+        let on_rename = Callback::from(move |name: String| {
+            if projects::saved_names().contains(&name) {
+                log!(format!("There is already a project called {}", name));
+                return
+            }
+            projects::delete(&cloned_state.name);
+            cloned_state.set(DelayGraphData { name, ..cloned_state.deref().clone() })
+        });
+        let cloned_state = state.clone();
+        let on_new = Callback::from(move |_| {
+            cloned_state.set(DelayGraphData::new(projects::unused_name(), empty_project()))
+        });
+        let cloned_state = state.clone();
+        let on_delete = Callback::from(move |_| {
+            projects::delete(&cloned_state.name);
+            let name = projects::saved_names().into_iter().next().unwrap_or_else(projects::unused_name);
+            let project = projects::load(&name).unwrap_or_else(empty_project);
+            cloned_state.set(DelayGraphData::new(name, project))
+        });
+        let cloned_state = state.clone();
+        let on_reset = Callback::from(move |_| {
+            cloned_state.set(DelayGraphData::new(cloned_state.name.clone(), empty_project()))
+        });
+        html! {
+            <ProjectBar current={state.name.clone()} onselect={on_select} onrename={on_rename} onnew={on_new} ondelete={on_delete} onreset={on_reset} />
+        }
+    };
+
+    let terms = state.clicked_time.and_then(|cell| derivation(&state.project, cell)).unwrap_or_default();
+    // cells whose entries, or the ends of whose delays, make up the derivation
+    let highlighted = terms.iter()
+        .flat_map(|term| [term.quantity.from, term.quantity.to])
+        .filter_map(|key| match key.event {
+            delays::Event::Event(event) => Some((event, key.timebase)),
+            delays::Event::T0 => None,
+        })
+        .collect::<Vec<_>>();
 
     let cloned_state = state.clone();
     let time_html = {
         let DelayGraphData {
-            events,
-            timebases,
+            project,
             ..
         } = cloned_state.deref().to_owned();
 
-        let events_html = events.iter().enumerate().map(|(i, text)| {
-           // Create callback which updates and emits state when the box is updated
-           let cloned_state = state.clone();
-           let on_change = Callback::from(move |text: String| {
-                let mut events = cloned_state.events.to_owned();
-                events[i] = text;
-                cloned_state.set( DelayGraphData {
-                    events,
-                    ..cloned_state.deref().clone()
-                })
-            });
-            html! {<td><TextInput text={text.clone()} onchange={on_change} /></td>}
+        let events_html = project.events.iter().map(|label| {
+            // Create callback which updates and emits state when the box is updated
+            let on_change = rename_callback(&state, Axis::Event, label.id);
+            let controls = label_controls(&state, Axis::Event, label.id);
+            html! {<td key={label.id}><TextInput text={label.name.clone()} onchange={on_change} />{controls}</td>}
         }).collect::<Html>();
 
-        let top_row_html = html!(<tr><td></td>{events_html}</tr>);
-
-        let timebases_iterable = timebases.iter().enumerate().map(|(j, text)| {
-            let cloned_state = state.clone();
-            let on_change = Callback::from(move |text: String| {
-                let mut timebases = cloned_state.timebases.to_owned();
-                timebases[j] = text;
-                cloned_state.set( DelayGraphData {
-                    timebases,
-                    ..cloned_state.deref().clone()
-                })
-            });
-            html!( <td><TextInput text={text.clone()} onchange={on_change} /></td>)
+        let cloned_state = state.clone();
+        let on_add_event = Callback::from(move |_: MouseEvent| {
+            let mut data = cloned_state.deref().clone();
+            let name = unused_name(&data.project.events, "event");
+            data.project.add_event(&name).unwrap();
+            cloned_state.set(data)
         });
 
-        let time_array_iterable = (0..timebases.len()).map(|j| {
+        let top_row_html = html!(<tr><td></td>{events_html}<td><button onclick={on_add_event}>{"+ event"}</button></td></tr>);
+
+        let connected = connected_to(&project.graph, state.clicked_time);
+        let other_rows_html = project.timebases.iter().map(|timebase| {
+            let j = timebase.id;
+            let on_change = rename_callback(&state, Axis::Timebase, j);
+            let controls = label_controls(&state, Axis::Timebase, j);
+            let timebase_html = html!( <td><TextInput text={timebase.name.clone()} onchange={on_change} />{controls}</td>);
+
             // generate html from the row
-            (0..events.len()).map(|i| {
-                
+            let time_array_html = project.events.iter().map(|event| {
+                let i = event.id;
+
                 // create a callback for when time is updated
                 let cloned_state = state.clone();
-                let on_change = Callback::from(move |num| {
-                    let DelayGraphData {
-                        mut event_graph,
-                        ..
-                    } = cloned_state.deref().clone();
-                    // let mut times = times.to_owned();
-                    // times[j][i] = num;
-                    if let Some(n) = num {
-                        log!("Add the event!", j, i, n);
-                        event_graph.update_time(j, i, n);
-                    } else {
-                        log!("Remove the event", j, i);
-                        event_graph.remove_time(j, i);
-                    }
-                    cloned_state.set(
-                        DelayGraphData {
-                            event_graph,
-                            ..cloned_state.deref().clone()
-                        }
-                    )
-                });
-
-                // create a callback record clicked and control clicked boxes
-                let cloned_state = state.clone();
-                let on_click = Callback::from(move |event: MouseEvent| {
-                    if event.meta_key() || event.ctrl_key() || event.shift_key() {
-                        cloned_state.set(
-                            DelayGraphData {
-                                control_clicked_time: Some((i, j)),
-                                ..cloned_state.deref().clone()
-                            }
-                        )
-                    } else {
-                        cloned_state.set(
-                            DelayGraphData {
-                                clicked_time: Some((i, j)),
-                                control_clicked_time: None,
-                                ..cloned_state.deref().clone()
-                            }
-                        )
-                    }
-                });
+                let on_change = enter_callback(&cloned_state, Quantity::time(j, i));
 
-                let cloned_state = state.clone();
-                let DelayGraphData {
-                    mut event_graph,
-                    clicked_time,
-                    ..
-                } = cloned_state.deref().clone();
+                let on_click = click_callback(&state, i, j);
+
+                let event_graph = &project.graph;
                 // Check if it links to selected time
-                
-                let is_connected = if let Some((i_clicked, j_clicked)) = clicked_time {
-                    let delay = event_graph.calculate_delay(j, delays::Event::Event(i), j_clicked, delays::Event::Event(i_clicked));
-                    if (j, i) == (j_clicked, i_clicked) {
-                        true
-                    } else {
-                    delay.is_some()
-                    }
-                } else {
-                    false
-                };
+                let is_connected = is_connected(&connected, (i, j));
                 // Get the value to display
-                let (value, editable) = if let Some(num) = event_graph.lookup_time(j, i) {
-                    (Some(*num), true)
+                // derived values can still be typed over, to be told what they conflict with
+                let (value, derived) = if let Some(num) = event_graph.lookup_time(j, i) {
+                    (Some(*num), false)
                 } else {
                     if let Some(num) = event_graph.calculate_time(j, i) {
-                        (Some(num), false)
+                        (Some(num), true)
                     } else {
-                        (None, true)
-                    }                    
+                        (None, false)
+                    }
                 };
                 let neighbors = event_graph.neighbors(j, i);
-                html!(<td><NumberInput value={value} editable={editable} neighbors={neighbors} is_connected={is_connected} onchange={on_change} onclick={on_click} /></td>)
-            }).collect::<Html>()
-        });
+                let highlighted = highlighted.contains(&(i, j));
+                let rejection = rejection_html(&state, |quantity| *quantity == Quantity::time(j, i));
+                html!(<td key={i}><NumberInput value={value} editable={true} derived={derived} neighbors={neighbors} is_connected={is_connected} highlighted={highlighted} onchange={on_change} onclick={on_click} />{rejection}</td>)
+            }).collect::<Html>();
 
-        let other_rows_html = timebases_iterable.zip(time_array_iterable).map(|(timebase_html, time_array_html)| {
-            html!(<tr>{timebase_html}{time_array_html}</tr>)
+            html!(<tr key={j}>{timebase_html}{time_array_html}</tr>)
         }).collect::<Html>();
 
+        let cloned_state = state.clone();
+        let on_add_timebase = Callback::from(move |_: MouseEvent| {
+            let mut data = cloned_state.deref().clone();
+            let name = unused_name(&data.project.timebases, "timebase");
+            data.project.add_timebase(&name).unwrap();
+            cloned_state.set(data)
+        });
+
         html!{
             <table>
             {top_row_html}
             {other_rows_html}
+            <tr><td><button onclick={on_add_timebase}>{"+ timebase"}</button></td></tr>
             </table>
         }
     };
 
-    let cloned_state = state.clone();
-    let delay_html = {
-        let DelayGraphData {
-            events,
-            timebases,
-            clicked_time,
-            control_clicked_time,
-            ..
-        } = cloned_state.deref().to_owned();
-        let time_1_html = if let Some((i, j)) = clicked_time {
-            html! {
-                <>
-                <input value={events[i].to_owned()} />
-                <input value={timebases[j].to_owned()} />
-                </>
-            }
-        } else {
-            html!(<><input value={""}/><input value={""}/></>)
-        };
-        let time_2_html = if let Some((i, j)) = control_clicked_time {
-            html! {
-                <>
-                <input value={events[i].to_owned()} />
-                <input value={timebases[j].to_owned()} />
-                </>
-            }
-        } else {
-            html!(<><input value={""}/><input value={""}/></>)
-        };
+    let delay_html = delay_table(&state);
 
-        let cloned_state = state.clone();
-        let (value, editable) = {
-            let DelayGraphData {
-                clicked_time,
-                control_clicked_time,
-                event_graph,
-                ..
-            } = cloned_state.deref().clone();
-            if let (Some((e1, t1)), Some((e2, t2))) = (clicked_time, control_clicked_time) {
-                log!("Try to get the delay");
-                if let Some(num) = event_graph.lookup_delay(t1, e1, t2, e2) {
-                    (Some(*num), true)
-                } else {
-                    if let Some(num) = event_graph.calculate_delay(t1, delays::Event::Event(e1), t2, delays::Event::Event(e2)) {
-                        (Some(num), false)
-                    } else {
-                        (None, true)
-                    }                    
+    let cloned_state = state.clone();
+    let import_html = {
+        let on_import = Callback::from(move |files: Vec<(String, Result<String, String>)>| {
+            let mut data = cloned_state.deref().clone();
+            data.import_errors.clear();
+            let read: Vec<(&str, &str)> = files.iter().filter_map(|(name, text)| Some((name.as_str(), text.as_deref().ok()?))).collect();
+            let texts: Vec<&str> = read.iter().map(|&(_, text)| text).collect();
+            let results = data.project.import_csvs(&texts);
+            for (name, text) in &files {
+                if let Err(error) = text {
+                    data.import_errors.push(format!("{}: could not read: {}", name, error));
                 }
-            } else {
-                (None, false)
             }
-        };
-        let neighbors = 0;
-        let cloned_state = state.clone();
-        let onchange = Callback::from(move |num| {
-            let DelayGraphData {
-                clicked_time,
-                control_clicked_time,
-                mut event_graph,
-                ..
-            } = cloned_state.deref().clone();
-
-            if let (Some((e1, t1)), Some((e2, t2))) = (clicked_time, control_clicked_time) {
-                if let Some(n) = num {
-                    event_graph.update_delay(t1, e1, t2, e2, n);
-                } else {
-                    event_graph.remove_delay(t1, e1, t2, e2);
-                }
-            };
-            
-            cloned_state.set(
-                DelayGraphData {
-                    event_graph,
-                    ..cloned_state.deref().clone()
+            for ((name, _), result) in read.iter().zip(results) {
+                match result {
+                    Ok(errors) => data.import_errors.extend(errors.into_iter().map(|error| {
+                        format!("{} line {}: {}", name, error.line, error.message)
+                    })),
+                    Err(error) => data.import_errors.push(format!("{}: {}", name, error)),
                 }
-            )
+            }
+            cloned_state.set(data)
         });
-        let onclick = Callback::from(|_| ());
-
+        let errors_html = state.import_errors.iter().map(|error| html!(<li>{error}</li>)).collect::<Html>();
         html! {
             <>
-            {"From:"}{time_1_html}
-            {"To:"}{time_2_html}
-            {"Delay:"}<NumberInput value={value} editable={editable} neighbors={neighbors} is_connected={false} onchange={onchange} onclick={onclick}/>
+            <FileImport onload={on_import} />
+            <ul style="color: darkred;">{errors_html}</ul>
             </>
         }
     };
 
+    let export_html = export_buttons(&state.project);
+
+    let derivation_html = match state.clicked_time {
+        Some((i, j)) if !terms.is_empty() => {
+            let project = &state.project;
+            let total: f64 = terms.iter().map(|term| term.value).sum();
+            let terms_html = terms.iter().map(|term| {
+                let Term {quantity, value} = term;
+                html!(<li>{format!("{} → {}: {}", key_name(project, quantity.from), key_name(project, quantity.to), value)}</li>)
+            }).collect::<Html>();
+            html! {
+                <>
+                <p>{format!("{} = {}, the sum of", key_name(project, TimebaseEventKey::new(j, i)), total)}</p>
+                <ol>{terms_html}</ol>
+                </>
+            }
+        },
+        _ => html!(),
+    };
+
+    let graph_html = {
+        // clicking a delay opens it for editing, and picks out its row in the delay table
+        let on_edge = |from: (usize, usize), to: (usize, usize)| {
+            let cloned_state = state.clone();
+            Callback::from(move |_: MouseEvent| {
+                cloned_state.set(
+                    DelayGraphData {
+                        clicked_time: Some(from),
+                        selected_delay: Some(Quantity::delay(from.1, from.0, to.1, to.0)),
+                        ..cloned_state.deref().clone()
+                    }
+                )
+            })
+        };
+        let on_edit = |quantity| enter_callback(&state, quantity);
+        graph_view(&state.project, state.clicked_time, state.selected_delay, |i, j| click_callback(&state, i, j), on_edge, on_edit)
+    };
+
     html! {
         <>
+        {project_html}
+        {import_html}
+        {export_html}
         {time_html}
-        <p> {"Delay"} </p>
+        {derivation_html}
+        <p> {"Delays"} </p>
         {delay_html}
+        <p> {"Graph"} </p>
+        {graph_html}
+        <p> {"Timeline"} </p>
+        <Timeline lanes={lanes(&state.project)} />
         </>
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,46 @@
+use gloo::file::{Blob, ObjectUrl};
+use gloo::timers::callback::Timeout;
+use yew::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::HtmlElement;
+use delays::project::Project;
+
+use super::projects::share_link;
+
+/// Have the browser save `contents` as `file_name`.
+pub fn download(file_name: &str, mime_type: &str, contents: &str) {
+    let url = ObjectUrl::from(Blob::new_with_options(contents, Some(mime_type)));
+    let link = gloo::utils::document().create_element("a").unwrap();
+    link.set_attribute("href", &url).unwrap();
+    link.set_attribute("download", file_name).unwrap();
+    link.unchecked_into::<HtmlElement>().click();
+    // the download starts after this returns, so only revoke the url once it has
+    Timeout::new(1000, move || drop(url)).forget();
+}
+
+/// Download buttons for everything entered in `project` and the times derived from it, and a link to share it.
+pub fn export_buttons(project: &Project) -> Html {
+    let button = |label: &str, file_name: &'static str, mime_type: &'static str, export: fn(&Project) -> String| {
+        let project = project.clone();
+        let onclick = Callback::from(move |_: MouseEvent| download(file_name, mime_type, &export(&project)));
+        html!(<button onclick={onclick}>{label}</button>)
+    };
+    html! {
+        <p>
+            {"Export: "}
+            {button("Times CSV", "times.csv", "text/csv", Project::times_csv)}
+            {button("Delays CSV", "delays.csv", "text/csv", Project::delays_csv)}
+            {button("Project JSON", "project.json", "application/json", Project::to_json)}
+            {button("Derived times CSV", "derived_times.csv", "text/csv", Project::time_matrix_csv)}
+            {button("Python", "delays_offsets.py", "text/x-python", Project::python_snippet)}
+            <details>
+                <summary>{"Share link"}</summary>
+                <input type="text" readonly={true} size="80" value={share_link(project)} />
+            </details>
+            <details>
+                <summary>{"Python snippet"}</summary>
+                <pre>{project.python_snippet()}</pre>
+            </details>
+        </p>
+    }
+}
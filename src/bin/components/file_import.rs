@@ -0,0 +1,70 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use gloo::file::callbacks::{read_as_text, FileReader};
+use gloo::file::File;
+use yew::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{DragEvent, HtmlInputElement};
+
+#[derive(Properties, PartialEq, Clone)]
+pub struct FileImportProps {
+    /// Called with the name of every file picked, in the order picked, with its contents or why it could not be read.
+    pub onload: Callback<Vec<(String, Result<String, String>)>>,
+}
+
+/// File picker that also accepts files dropped onto it.
+#[function_component(FileImport)]
+pub fn file_import(FileImportProps { onload }: &FileImportProps) -> Html {
+
+    // readers stop when dropped, so keep them until the next import
+    let readers = use_mut_ref(Vec::<FileReader>::new);
+
+    let read_files = {
+        let onload = onload.clone();
+        move |files: Option<web_sys::FileList>| {
+            let mut readers = readers.borrow_mut();
+            readers.clear();
+            let files: Vec<File> = files.map(|files| gloo::file::FileList::from(files).to_vec()).unwrap_or_default();
+            // hand over every file at once, so each import starts from the last
+            let remaining = Rc::new(Cell::new(files.len()));
+            let loaded = Rc::new(RefCell::new(vec![None; files.len()]));
+            for (i, file) in files.into_iter().enumerate() {
+                let name = file.name();
+                let onload = onload.clone();
+                let loaded = loaded.clone();
+                let remaining = remaining.clone();
+                readers.push(read_as_text(&file, move |result| {
+                    // reads finish in any order, so keep each in the slot it was picked in
+                    loaded.borrow_mut()[i] = Some((name, result.map_err(|error| error.to_string())));
+                    remaining.set(remaining.get() - 1);
+                    if remaining.get() == 0 {
+                        onload.emit(loaded.take().into_iter().flatten().collect());
+                    }
+                }));
+            }
+        }
+    };
+
+    let on_change = {
+        let read_files = read_files.clone();
+        Callback::from(move |event: Event| {
+            let input = event.target().unwrap().unchecked_into::<HtmlInputElement>();
+            read_files(input.files());
+            // let the same file be picked again after it has been edited
+            input.set_value("");
+        })
+    };
+    let on_drop = Callback::from(move |event: DragEvent| {
+        event.prevent_default();
+        read_files(event.data_transfer().and_then(|transfer| transfer.files()));
+    });
+    let on_drag_over = Callback::from(|event: DragEvent| event.prevent_default());
+
+    html! {
+        <div style="border: 2px dashed gray; padding: 1em;" ondrop={on_drop} ondragover={on_drag_over}>
+            {"Import times or delays CSV, or drop files here: "}
+            <input type="file" accept=".csv,text/csv" multiple={true} onchange={on_change} />
+        </div>
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::BTreeSet;
+
+use yew::prelude::*;
+use delays::{DelayGraph, Event, Quantity, TimebaseEventKey};
+use delays::project::Project;
+
+use super::number_input::NumberInput;
+
+const LANE_HEIGHT: f64 = 80.0;
+const EVENT_SPACING: f64 = 110.0;
+const MARGIN: f64 = 100.0;
+const RADIUS: f64 = 9.0;
+
+/// Every node linked to the `clicked` one, (event id, timebase id) as in the grid, found once per render.
+pub fn connected_to(graph: &DelayGraph, clicked: Option<(usize, usize)>) -> BTreeSet<TimebaseEventKey> {
+    clicked.map_or_else(BTreeSet::new, |(i, j)| graph.connected(j, Event::Event(i)))
+}
+
+/// Whether the time at `cell` is among the `connected` ones.
+pub fn is_connected(connected: &BTreeSet<TimebaseEventKey>, (i, j): (usize, usize)) -> bool {
+    connected.contains(&TimebaseEventKey::new(j, i))
+}
+
+/// Fill colour of a node, matching the background of its grid cell.
+fn fill(neighbors: usize) -> &'static str {
+    match neighbors {
+        0 => "white",
+        1 => "orange",
+        2 => "lightgreen",
+        _ => "green",
+    }
+}
+
+/// SVG drawing of `project` with a lane per timebase, a node per event on it and an edge per entered delay.
+/// `on_node` and `on_edge` make the callbacks for clicks on a node, (event id, timebase id), or on a delay between two nodes.
+/// The `selected` delay is shown in a box, which enters what is typed into it through `on_edit`.
+pub fn graph_view(
+    project: &Project,
+    clicked: Option<(usize, usize)>,
+    selected: Option<Quantity>,
+    on_node: impl Fn(usize, usize) -> Callback<MouseEvent>,
+    on_edge: impl Fn((usize, usize), (usize, usize)) -> Callback<MouseEvent>,
+    on_edit: impl Fn(Quantity) -> Callback<Option<f64>>,
+) -> Html {
+    let graph = &project.graph;
+    let connected = connected_to(graph, clicked);
+    let x = |event: usize| MARGIN + EVENT_SPACING * project.events.position(event).unwrap() as f64;
+    let y = |timebase: usize| LANE_HEIGHT * (project.timebases.position(timebase).unwrap() as f64 + 0.5);
+    let position = |(i, j): (usize, usize)| (x(i), y(j));
+    let width = MARGIN + EVENT_SPACING * project.events.len() as f64;
+    let height = LANE_HEIGHT * project.timebases.len() as f64;
+
+    let lanes_html = project.timebases.iter().map(|timebase| {
+        let y = y(timebase.id);
+        html! {
+            <g key={timebase.id}>
+                <line x1={MARGIN.to_string()} y1={y.to_string()} x2={width.to_string()} y2={y.to_string()} stroke="lightgray" />
+                <text x="4" y={(y + 4.0).to_string()}>{&timebase.name}</text>
+            </g>
+        }
+    }).collect::<Html>();
+
+    let edges_html = graph.delays().into_iter().map(|(t1, e1, t2, e2, delay)| {
+        let ((x1, y1), (x2, y2)) = (position((e1, t1)), position((e2, t2)));
+        // bow the edge, so delays along one lane do not hide it
+        let (cx, cy) = ((x1 + x2) / 2.0 + (y2 - y1) * 0.2, (y1 + y2) / 2.0 - (x2 - x1) * 0.2 - 10.0);
+        let path = format!("M {} {} Q {} {} {} {}", x1, y1, cx, cy, x2, y2);
+        let highlighted = is_connected(&connected, (e1, t1));
+        let colour = if highlighted { "green" } else { "gray" };
+        let onclick = on_edge((e1, t1), (e2, t2));
+        let quantity = Quantity::delay(t1, e1, t2, e2);
+        // the middle of the curve, where the value goes
+        let (lx, ly) = ((x1 + x2) / 4.0 + cx / 2.0, (y1 + y2) / 4.0 + cy / 2.0);
+        let value_html = if selected == Some(quantity) {
+            html! {
+                <foreignObject x={lx.to_string()} y={(ly - 20.0).to_string()} width="80" height="24">
+                    <NumberInput value={Some(delay)} editable={true} neighbors={0} is_connected={false} onchange={on_edit(quantity)} onclick={Callback::from(|_| ())} />
+                </foreignObject>
+            }
+        } else {
+            html!(<text x={lx.to_string()} y={(ly - 3.0).to_string()} font-size="11" fill={colour}>{delay}</text>)
+        };
+        html! {
+            <g key={format!("{} {} {} {}", t1, e1, t2, e2)} onclick={onclick} style="cursor: pointer;">
+                <title>{format!("{} → {}: {}", cell_name(project, (e1, t1)), cell_name(project, (e2, t2)), delay)}</title>
+                <path d={path.clone()} fill="none" stroke="transparent" stroke-width="12" />
+                <path d={path} fill="none" stroke={colour} stroke-width={if highlighted { "2.5" } else { "1.5" }} />
+                {value_html}
+            </g>
+        }
+    }).collect::<Html>();
+
+    let nodes_html = project.timebases.iter().flat_map(|timebase| project.events.iter().map(move |event| (event.id, timebase.id))).map(|(i, j)| {
+        let (x, y) = position((i, j));
+        let (label, weight) = match (graph.lookup_time(j, i), graph.calculate_time(j, i)) {
+            (Some(time), _) => (time.to_string(), "bold"),
+            (None, Some(time)) => (time.to_string(), "normal"),
+            (None, None) => (String::new(), "normal"),
+        };
+        let stroke = if is_connected(&connected, (i, j)) { "green" } else { "black" };
+        html! {
+            <g key={format!("{} {}", i, j)} onclick={on_node(i, j)} style="cursor: pointer;">
+                <title>{cell_name(project, (i, j))}</title>
+                <circle cx={x.to_string()} cy={y.to_string()} r={RADIUS.to_string()} fill={fill(graph.neighbors(j, i))} stroke={stroke} stroke-width="2" />
+                <text x={x.to_string()} y={(y + RADIUS + 13.0).to_string()} font-size="11" font-weight={weight} text-anchor="middle">{label}</text>
+            </g>
+        }
+    }).collect::<Html>();
+
+    let events_html = project.events.iter().map(|event| {
+        html!(<text key={event.id} x={x(event.id).to_string()} y="12" text-anchor="middle">{&event.name}</text>)
+    }).collect::<Html>();
+
+    html! {
+        <svg width={width.to_string()} height={(height + 20.0).to_string()} style="font-family: sans-serif;">
+            <g transform="translate(0, 20)">
+                {lanes_html}
+                {edges_html}
+                {nodes_html}
+            </g>
+            {events_html}
+        </svg>
+    }
+}
+
+fn cell_name(project: &Project, (i, j): (usize, usize)) -> String {
+    format!("{} on {}", project.events.name(i).unwrap_or_default(), project.timebases.name(j).unwrap_or_default())
+}
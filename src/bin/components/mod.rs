@@ -1,4 +1,9 @@
 pub mod text_input;
 pub mod number_input;
 pub mod text_input_list;
+pub mod file_import;
+pub mod export_buttons;
+pub mod projects;
+pub mod graph_view;
+pub mod timeline;
 pub mod event_graph_widget;
\ No newline at end of file
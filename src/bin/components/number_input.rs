@@ -17,12 +17,18 @@ pub struct NumberInputProps {
     pub editable: bool,
     pub neighbors: usize,
     pub is_connected: bool,
+    /// Whether the value shown is derived from other entries rather than entered
+    #[prop_or_default]
+    pub derived: bool,
+    /// Whether the value is part of the derivation being shown
+    #[prop_or_default]
+    pub highlighted: bool,
     pub onchange: Callback<Option<f64>>,
     pub onclick: Callback<MouseEvent>,
 }
 
 #[function_component(NumberInput)]
-pub fn number_input(NumberInputProps{ value, editable, neighbors, is_connected, onchange, onclick }: &NumberInputProps) -> Html {
+pub fn number_input(NumberInputProps{ value, editable, derived, neighbors, is_connected, highlighted, onchange, onclick }: &NumberInputProps) -> Html {
     
     let onchange = onchange.clone();
     let onclick = onclick.clone();
@@ -66,7 +72,17 @@ pub fn number_input(NumberInputProps{ value, editable, neighbors, is_connected,
         false => "",
     };
 
-    let style_str = format!("{}\n{}", neighbors_css, connected_css);
+    let derived_css = match derived {
+        true => "font-style: italic; color: gray;",
+        false => "",
+    };
+
+    let highlighted_css = match highlighted {
+        true => "outline: 2px solid orange;",
+        false => "",
+    };
+
+    let style_str = format!("{}\n{}\n{}\n{}", neighbors_css, connected_css, derived_css, highlighted_css);
 
     if *editable {
         html! {
@@ -0,0 +1,134 @@
+use gloo::storage::{LocalStorage, Storage};
+use yew::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::HtmlSelectElement;
+use delays::project::{Project, ProjectData};
+
+use super::text_input::TextInput;
+
+const INDEX_KEY: &str = "delays.projects";
+const CURRENT_KEY: &str = "delays.current";
+
+fn project_key(name: &str) -> String {
+    format!("delays.project.{}", name)
+}
+
+/// Names of the projects saved in this browser.
+pub fn saved_names() -> Vec<String> {
+    LocalStorage::get(INDEX_KEY).unwrap_or_default()
+}
+
+/// Name of the project open when the page was last used.
+pub fn current_name() -> Option<String> {
+    LocalStorage::get(CURRENT_KEY).ok()
+}
+
+pub fn load(name: &str) -> Option<Project> {
+    let data: ProjectData = LocalStorage::get(project_key(name)).ok()?;
+    match Project::from_data(&data) {
+        Ok(project) => Some(project),
+        Err(error) => {
+            gloo::console::log!(format!("Could not restore {}: {}", name, error));
+            None
+        },
+    }
+}
+
+/// Save `project` as `name` and make it the one opened next time.
+pub fn save(name: &str, project: &Project) {
+    let mut names = saved_names();
+    if !names.iter().any(|saved| saved == name) {
+        names.push(name.to_owned());
+    }
+    let result = LocalStorage::set(project_key(name), project.to_data())
+        .and_then(|_| LocalStorage::set(INDEX_KEY, names))
+        .and_then(|_| LocalStorage::set(CURRENT_KEY, name));
+    if let Err(error) = result {
+        gloo::console::log!(format!("Could not save {}: {}", name, error));
+    }
+}
+
+pub fn delete(name: &str) {
+    let names: Vec<String> = saved_names().into_iter().filter(|saved| saved != name).collect();
+    LocalStorage::delete(project_key(name));
+    if let Err(error) = LocalStorage::set(INDEX_KEY, names) {
+        gloo::console::log!(format!("Could not delete {}: {}", name, error));
+    }
+}
+
+/// Address that opens a copy of `project`.
+pub fn share_link(project: &Project) -> String {
+    let location = gloo::utils::window().location();
+    let page = location.href().unwrap_or_default();
+    let page = page.split('#').next().unwrap_or_default();
+    format!("{}#{}", page, project.to_link())
+}
+
+/// The project in the page's link, if it was opened from one, removing it from the address bar.
+pub fn shared_project() -> Option<Project> {
+    let fragment = gloo::utils::window().location().hash().ok().filter(|hash| !hash.is_empty())?;
+    let location = gloo::utils::window().location();
+    let page = format!("{}{}", location.pathname().unwrap_or_default(), location.search().unwrap_or_default());
+    // edits are saved locally, so a reload should not bring the shared copy back
+    let _ = gloo::utils::history().replace_state_with_url(&wasm_bindgen::JsValue::NULL, "", Some(&page));
+    match Project::from_link(&fragment) {
+        Ok(project) => Some(project),
+        Err(error) => {
+            gloo::console::log!(format!("Could not open the shared project: {}", error));
+            None
+        },
+    }
+}
+
+/// First name of the form "project n" not saved yet.
+pub fn unused_name() -> String {
+    let names = saved_names();
+    (1..).map(|n| format!("project {}", n)).find(|name| !names.contains(name)).unwrap()
+}
+
+#[derive(Properties, PartialEq, Clone)]
+pub struct ProjectBarProps {
+    pub current: String,
+    pub onselect: Callback<String>,
+    pub onrename: Callback<String>,
+    pub onnew: Callback<()>,
+    pub ondelete: Callback<()>,
+    pub onreset: Callback<()>,
+}
+
+/// Switch between, rename, add, delete and reset the saved projects.
+#[function_component(ProjectBar)]
+pub fn project_bar(ProjectBarProps { current, onselect, onrename, onnew, ondelete, onreset }: &ProjectBarProps) -> Html {
+    let mut names = saved_names();
+    if !names.contains(current) {
+        names.push(current.clone());
+    }
+    let options = names.iter().map(|name| {
+        html!(<option value={name.clone()} selected={name == current}>{name}</option>)
+    }).collect::<Html>();
+
+    let onselect = onselect.clone();
+    let on_change = Callback::from(move |event: Event| {
+        let select = event.target().unwrap().unchecked_into::<HtmlSelectElement>();
+        onselect.emit(select.value())
+    });
+    let button = |callback: &Callback<()>| callback.reform(|_: MouseEvent| ());
+    let onreset = onreset.clone();
+    let on_reset = Callback::from(move |_: MouseEvent| {
+        if gloo::dialogs::confirm("Clear every name, time and delay in this project?") {
+            onreset.emit(())
+        }
+    });
+
+    html! {
+        <p>
+            {"Project: "}
+            <select onchange={on_change}>{options}</select>
+            {" Name: "}
+            <TextInput key={current.clone()} text={current.clone()} onchange={onrename.clone()} />
+            <button onclick={button(onnew)}>{"New"}</button>
+            <button onclick={button(ondelete)}>{"Delete"}</button>
+            <button onclick={on_reset}>{"Reset"}</button>
+        </p>
+    }
+}
@@ -0,0 +1,198 @@
+use yew::prelude::*;
+use delays::project::Project;
+
+const WIDTH: f64 = 800.0;
+const MARGIN: f64 = 100.0;
+const LANE_HEIGHT: f64 = 60.0;
+
+/// An event drawn on a timebase at its entered or derived time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimelineEvent {
+    pub id: usize,
+    pub name: String,
+    pub time: f64,
+    pub entered: bool,
+    /// Next lane down the same event is drawn on, if the graph relates the two.
+    pub linked_to: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lane {
+    pub name: String,
+    pub events: Vec<TimelineEvent>,
+}
+
+/// One lane per timebase with every time known on it, in display order.
+pub fn lanes(project: &Project) -> Vec<Lane> {
+    let timebases: Vec<usize> = project.timebases.iter().map(|timebase| timebase.id).collect();
+    let mut lanes: Vec<Lane> = project.timebases.iter().map(|timebase| {
+        let events = project.events.iter().filter_map(|event| {
+            let (time, entered) = match project.graph.lookup_time(timebase.id, event.id) {
+                Some(time) => (*time, true),
+                None => (project.graph.calculate_time(timebase.id, event.id)?, false),
+            };
+            Some(TimelineEvent { id: event.id, name: event.name.clone(), time, entered, linked_to: None })
+        }).collect();
+        Lane { name: timebase.name.clone(), events }
+    }).collect();
+
+    // sharing an event id says nothing of how the timebases line up, so only link through the graph
+    for n in 0..lanes.len() {
+        for k in 0..lanes[n].events.len() {
+            let id = lanes[n].events[k].id;
+            let related = |m: usize| project.graph.path(timebases[n], delays::Event::Event(id), timebases[m], delays::Event::Event(id)).is_some();
+            let linked_to = (n + 1..lanes.len()).find(|&m| lanes[m].events.iter().any(|event| event.id == id) && related(m));
+            lanes[n].events[k].linked_to = linked_to;
+        }
+    }
+    lanes
+}
+
+/// Tick spacing of 1, 2 or 5 times a power of ten giving about eight ticks over `span`.
+fn tick_step(span: f64) -> f64 {
+    let rough = span / 8.0;
+    let power = 10f64.powf(rough.log10().floor());
+    [1.0, 2.0, 5.0, 10.0].into_iter().map(|m| m * power).find(|step| *step >= rough).unwrap()
+}
+
+/// Times shown, as (start, span).
+type View = (f64, f64);
+
+/// View fitting every time in `lanes`, with some room either side.
+fn fit(lanes: &[Lane]) -> View {
+    let times = lanes.iter().flat_map(|lane| lane.events.iter().map(|event| event.time));
+    let (min, max) = times.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), t| (min.min(t), max.max(t)));
+    if min > max {
+        (0.0, 10.0)
+    } else {
+        let span = (max - min).max(1e-12);
+        (min - span * 0.1, span * 1.2)
+    }
+}
+
+#[derive(Properties, PartialEq, Clone)]
+pub struct TimelineProps {
+    pub lanes: Vec<Lane>,
+}
+
+/// A horizontal time axis per timebase with its events, and links between the same event on neighbouring
+/// timebases. The wheel zooms about the pointer and dragging pans.
+#[function_component(Timeline)]
+pub fn timeline(TimelineProps { lanes }: &TimelineProps) -> Html {
+
+    // None follows the data, until the user zooms or pans
+    let view = use_state(|| None::<View>);
+    let drag = use_mut_ref(|| None::<(i32, View)>);
+    let svg_ref = use_node_ref();
+    let (start, span) = view.unwrap_or_else(|| fit(lanes));
+
+    let x = move |time: f64| MARGIN + (time - start) / span * WIDTH;
+    let time_at = move |svg_x: f64| start + (svg_x - MARGIN) / WIDTH * span;
+    let y = |lane: usize| LANE_HEIGHT * (lane as f64 + 0.5);
+
+    let on_wheel = {
+        let view = view.clone();
+        let svg_ref = svg_ref.clone();
+        Callback::from(move |event: WheelEvent| {
+            event.prevent_default();
+            let factor = if event.delta_y() > 0.0 { 1.25 } else { 0.8 };
+            // offsets of svg events depend on the shape under the pointer, so measure from the svg itself
+            let left = svg_ref.cast::<web_sys::Element>().map_or(0.0, |svg| svg.get_bounding_client_rect().left());
+            let anchor = time_at(event.client_x() as f64 - left);
+            view.set(Some((anchor - (anchor - start) * factor, span * factor)))
+        })
+    };
+    let on_mouse_down = {
+        let drag = drag.clone();
+        Callback::from(move |event: MouseEvent| *drag.borrow_mut() = Some((event.client_x(), (start, span))))
+    };
+    let on_mouse_move = {
+        let view = view.clone();
+        let drag = drag.clone();
+        Callback::from(move |event: MouseEvent| {
+            if let Some((from_x, (start, span))) = *drag.borrow() {
+                let moved = (event.client_x() - from_x) as f64 / WIDTH * span;
+                view.set(Some((start - moved, span)))
+            }
+        })
+    };
+    let on_mouse_up = {
+        let drag = drag.clone();
+        Callback::from(move |_: MouseEvent| *drag.borrow_mut() = None)
+    };
+    let zoom = |factor: f64| {
+        let view = view.clone();
+        Callback::from(move |_: MouseEvent| {
+            let centre = start + span / 2.0;
+            view.set(Some((centre - span * factor / 2.0, span * factor)))
+        })
+    };
+    let on_fit = {
+        let view = view.clone();
+        Callback::from(move |_: MouseEvent| view.set(None))
+    };
+
+    let step = tick_step(span);
+    let decimals = (-step.log10().floor()).max(0.0) as usize;
+    let ticks = ((start / step).ceil() as i64..=((start + span) / step).floor() as i64).map(|n| n as f64 * step);
+    let height = LANE_HEIGHT * lanes.len() as f64;
+
+    let lanes_html = lanes.iter().enumerate().map(|(n, lane)| {
+        let y = y(n);
+        let ticks_html = ticks.clone().map(|time| html! {
+            <>
+            <line x1={x(time).to_string()} y1={(y - 4.0).to_string()} x2={x(time).to_string()} y2={(y + 4.0).to_string()} stroke="gray" />
+            <text x={x(time).to_string()} y={(y + 16.0).to_string()} font-size="10" fill="gray" text-anchor="middle">{format!("{:.*}", decimals, time)}</text>
+            </>
+        }).collect::<Html>();
+        let events_html = lane.events.iter().map(|event| html! {
+            <g key={event.id}>
+                <title>{format!("{}: {}", event.name, event.time)}</title>
+                <line x1={x(event.time).to_string()} y1={(y - 12.0).to_string()} x2={x(event.time).to_string()} y2={y.to_string()}
+                    stroke="black" stroke-width={if event.entered { "3" } else { "1.5" }} />
+                <text x={x(event.time).to_string()} y={(y - 15.0).to_string()} font-size="11" text-anchor="middle"
+                    font-weight={if event.entered { "bold" } else { "normal" }}>{&event.name}</text>
+            </g>
+        }).collect::<Html>();
+        html! {
+            <g>
+                <line x1={MARGIN.to_string()} y1={y.to_string()} x2={(MARGIN + WIDTH).to_string()} y2={y.to_string()} stroke="black" />
+                {ticks_html}
+                {events_html}
+            </g>
+        }
+    }).collect::<Html>();
+
+    let names_html = lanes.iter().enumerate().map(|(n, lane)| {
+        html!(<text x="4" y={(y(n) + 4.0).to_string()}>{&lane.name}</text>)
+    }).collect::<Html>();
+
+    // the same event on the next timebase it is related to
+    let links_html = lanes.iter().enumerate().flat_map(|(n, lane)| lane.events.iter().filter_map(move |event| {
+        let m = event.linked_to?;
+        let other = lanes[m].events.iter().find(|e| e.id == event.id)?;
+        Some(html! {
+            <line x1={x(event.time).to_string()} y1={y(n).to_string()} x2={x(other.time).to_string()} y2={(y(m) - 12.0).to_string()}
+                stroke="steelblue" stroke-dasharray="4 3" />
+        })
+    })).collect::<Html>();
+
+    html! {
+        <div>
+            <button onclick={zoom(0.5)}>{"+"}</button>
+            <button onclick={zoom(2.0)}>{"−"}</button>
+            <button onclick={on_fit}>{"Fit"}</button>
+            <br />
+            <svg ref={svg_ref} width={(MARGIN + WIDTH + 20.0).to_string()} height={height.to_string()} style="font-family: sans-serif; cursor: grab;"
+                onwheel={on_wheel} onmousedown={on_mouse_down} onmousemove={on_mouse_move} onmouseup={on_mouse_up.clone()} onmouseleave={on_mouse_up}>
+                <defs>
+                    <clipPath id="timeline-axes">
+                        <rect x={(MARGIN - 10.0).to_string()} y="0" width={(WIDTH + 20.0).to_string()} height={height.to_string()} />
+                    </clipPath>
+                </defs>
+                {names_html}
+                <g clip-path="url(#timeline-axes)">{lanes_html}{links_html}</g>
+            </svg>
+        </div>
+    }
+}
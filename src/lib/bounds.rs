@@ -0,0 +1,294 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::{DelayGraph, Errors, Event, TimebaseEventKey};
+
+/// Slack allowed when relaxing edges, so that rounding in sums of exact
+/// delays is not mistaken for an infeasible loop.
+const TOLERANCE: f64 = 1e-9;
+
+/// A closed range of values, either end of which may be infinite.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Interval {
+    pub fn new(min: f64, max: f64) -> Self {
+        Self {min, max}
+    }
+
+    pub fn unbounded() -> Self {
+        Self::new(f64::NEG_INFINITY, f64::INFINITY)
+    }
+
+    pub fn contains(&self, value: f64) -> bool {
+        self.min - TOLERANCE <= value && value <= self.max + TOLERANCE
+    }
+
+    pub fn is_bounded(&self) -> bool {
+        self.min.is_finite() && self.max.is_finite()
+    }
+
+    /// Middle of the interval, or the finite end if only one end is finite.
+    pub fn midpoint(&self) -> Option<f64> {
+        match (self.min.is_finite(), self.max.is_finite()) {
+            (true, true) => Some(0.5 * (self.min + self.max)),
+            (true, false) => Some(self.min),
+            (false, true) => Some(self.max),
+            (false, false) => None,
+        }
+    }
+}
+
+pub(crate) type Edge = (TimebaseEventKey, TimebaseEventKey, f64);
+
+impl DelayGraph {
+
+    /// Constrain the delay from `event_1` on `timebase_1` to `event_2` on `timebase_2`
+    /// to lie between `min` and `max`. Either limit may be infinite.
+    pub fn add_bound(&mut self, timebase_1: usize, event_1: usize, timebase_2: usize, event_2: usize, min: f64, max: f64) -> Result<(), Errors> {
+        let key_1 = TimebaseEventKey::new(timebase_1, event_1);
+        let key_2 = TimebaseEventKey::new(timebase_2, event_2);
+        self.add_bound_keys(key_1, key_2, Interval::new(min, max))
+    }
+
+    pub(crate) fn add_bound_keys(&mut self, key_1: TimebaseEventKey, key_2: TimebaseEventKey, interval: Interval) -> Result<(), Errors> {
+        if interval.min.is_nan() || interval.max.is_nan() || interval.min > interval.max {
+            return Err(Errors::InvalidBound)
+        }
+        if self.bounds.contains_key(&(key_1, key_2)) || self.bounds.contains_key(&(key_2, key_1)) {
+            return Err(Errors::AlreadyExists)
+        }
+        self.bounds.insert((key_1, key_2), interval);
+        Ok(())
+    }
+
+    pub fn remove_bound(&mut self, timebase_1: usize, event_1: usize, timebase_2: usize, event_2: usize) -> Result<(), Errors> {
+        let key_1 = TimebaseEventKey::new(timebase_1, event_1);
+        let key_2 = TimebaseEventKey::new(timebase_2, event_2);
+        if self.bounds.remove(&(key_1, key_2)).is_some() || self.bounds.remove(&(key_2, key_1)).is_some() {
+            Ok(())
+        } else {
+            Err(Errors::NotFound)
+        }
+    }
+
+    /// The bound on the delay from `event_1` on `timebase_1` to `event_2` on `timebase_2`,
+    /// negated if it was entered the other way round.
+    pub fn lookup_bound(&self, timebase_1: usize, event_1: usize, timebase_2: usize, event_2: usize) -> Option<Interval> {
+        let key_1 = TimebaseEventKey::new(timebase_1, event_1);
+        let key_2 = TimebaseEventKey::new(timebase_2, event_2);
+        self.bound_between(key_1, key_2)
+    }
+
+    /// The bound on the delay from `key_1` to `key_2`, whichever way round it was entered.
+    pub(crate) fn bound_between(&self, key_1: TimebaseEventKey, key_2: TimebaseEventKey) -> Option<Interval> {
+        match self.bounds.get(&(key_1, key_2)) {
+            Some(interval) => Some(*interval),
+            None => self.bounds.get(&(key_2, key_1)).map(|interval| Interval::new(-interval.max, -interval.min)),
+        }
+    }
+
+    /// Every time, delay, bound, channel range and physical link written as `to - from <= weight`.
+    /// Exact values already appear as a pair of opposing edges in the graph.
+    pub(crate) fn constraint_edges(&self) -> Vec<Edge> {
+        let mut edges: Vec<Edge> = self.graph.all_edges().map(|(a, b, w)| (a, b, *w)).collect();
+        for (&(key_1, key_2), interval) in self.bounds.iter().chain(&self.channels) {
+            if interval.max.is_finite() {
+                edges.push((key_1, key_2, interval.max));
+            }
+            if interval.min.is_finite() {
+                edges.push((key_2, key_1, -interval.min));
+            }
+        }
+        edges.extend(self.physical_edges());
+        edges
+    }
+
+    /// All nodes touched by `edges` or listed in `extra`,
+    /// plus the T0 of every timebase they mention.
+    pub(crate) fn constraint_nodes(edges: &[Edge], extra: &[TimebaseEventKey]) -> Vec<TimebaseEventKey> {
+        let mut nodes = BTreeSet::new();
+        let ends = edges.iter().flat_map(|(a, b, _)| [a, b]);
+        for key in ends.chain(extra) {
+            nodes.insert(*key);
+            nodes.insert(key.t0_key());
+        }
+        nodes.into_iter().collect()
+    }
+
+    /// Check that the times, delays and bounds can all be satisfied at once.
+    /// If not, the offending loop of constraints is returned in `Errors::Infeasible`.
+    pub fn check_bounds(&self) -> Result<(), Errors> {
+        let edges = self.constraint_edges();
+        let nodes = Self::constraint_nodes(&edges, &[]);
+        bellman_ford(&nodes, &edges, None).map(|_| ()).map_err(Errors::Infeasible)
+    }
+
+    /// Feasible range of the delay between two events under all times, delays and bounds.
+    pub fn feasible_delay(&self, timebase_1: usize, event_1: Event<usize>, timebase_2: usize, event_2: Event<usize>) -> Result<Interval, Errors> {
+        let key_1 = TimebaseEventKey {timebase: timebase_1, event: event_1};
+        let key_2 = TimebaseEventKey {timebase: timebase_2, event: event_2};
+        let edges = self.constraint_edges();
+        let nodes = Self::constraint_nodes(&edges, &[key_1, key_2]);
+        bellman_ford(&nodes, &edges, None).map_err(Errors::Infeasible)?;
+
+        let upper = bellman_ford(&nodes, &edges, Some(key_1)).map_err(Errors::Infeasible)?;
+        let lower = bellman_ford(&nodes, &edges, Some(key_2)).map_err(Errors::Infeasible)?;
+
+        let max = upper.get(&key_2).copied().unwrap_or(f64::INFINITY);
+        let min = lower.get(&key_1).map(|d| -d).unwrap_or(f64::NEG_INFINITY);
+        Ok(Interval::new(min, max))
+    }
+
+    /// Solve the times, delays and bounds as a system of difference constraints,
+    /// returning the feasible range of every event time on its own timebase.
+    pub fn solve_bounds(&self) -> Result<BTreeMap<(usize, usize), Interval>, Errors> {
+        let edges = self.constraint_edges();
+        let nodes = Self::constraint_nodes(&edges, &[]);
+        bellman_ford(&nodes, &edges, None).map_err(Errors::Infeasible)?;
+
+        let reversed: Vec<Edge> = edges.iter().map(|&(a, b, w)| (b, a, w)).collect();
+        let mut ranges = BTreeMap::new();
+        for t0_key in nodes.iter().filter(|key| key.event == Event::T0) {
+            let upper = bellman_ford(&nodes, &edges, Some(*t0_key)).map_err(Errors::Infeasible)?;
+            let lower = bellman_ford(&nodes, &reversed, Some(*t0_key)).map_err(Errors::Infeasible)?;
+            for key in nodes.iter().filter(|key| key.timebase == t0_key.timebase) {
+                if let Event::Event(event) = key.event {
+                    let max = upper.get(key).copied().unwrap_or(f64::INFINITY);
+                    let min = lower.get(key).map(|d| -d).unwrap_or(f64::NEG_INFINITY);
+                    ranges.insert((key.timebase, event), Interval::new(min, max));
+                }
+            }
+        }
+        Ok(ranges)
+    }
+}
+
+/// Shortest distances from `source` over `edges`, or from a virtual source joined
+/// to every node when `source` is `None`. Unreachable nodes are left out.
+/// A negative loop is returned as the list of nodes around it.
+pub(crate) fn bellman_ford(nodes: &[TimebaseEventKey], edges: &[Edge], source: Option<TimebaseEventKey>) -> Result<HashMap<TimebaseEventKey, f64>, Vec<TimebaseEventKey>> {
+    let mut dist: HashMap<TimebaseEventKey, f64> = match source {
+        Some(source) => HashMap::from([(source, 0.0)]),
+        None => nodes.iter().map(|key| (*key, 0.0)).collect(),
+    };
+    let mut pred: HashMap<TimebaseEventKey, TimebaseEventKey> = HashMap::new();
+
+    for _ in 0..nodes.len() {
+        let mut changed = false;
+        for &(a, b, w) in edges {
+            if let Some(&d) = dist.get(&a) {
+                let shorter = match dist.get(&b) {
+                    Some(&current) => d + w < current - TOLERANCE,
+                    None => true,
+                };
+                if shorter {
+                    dist.insert(b, d + w);
+                    pred.insert(b, a);
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            return Ok(dist)
+        }
+    }
+
+    // one more pass to find a node whose distance still improves
+    for &(a, b, w) in edges {
+        if let (Some(&da), Some(&db)) = (dist.get(&a), dist.get(&b)) {
+            if da + w < db - TOLERANCE {
+                pred.insert(b, a);
+                // walk back far enough to be sure we are on the loop
+                let mut node = b;
+                for _ in 0..nodes.len() {
+                    node = pred[&node];
+                }
+                let mut cycle = vec![node];
+                let mut current = pred[&node];
+                while current != node {
+                    cycle.push(current);
+                    current = pred[&current];
+                }
+                cycle.reverse();
+                return Err(cycle)
+            }
+        }
+    }
+    Ok(dist)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    ///  0
+    ///  |------->   experiment
+    ///   \[50, 100]
+    ///    |----->   scope
+    ///    ?
+    fn bound_gives_range() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 0.0).unwrap();
+        event_graph.add_time(2, 1, 200.0).unwrap();
+        event_graph.add_bound(1, 1, 2, 1, 50.0, 100.0).unwrap();
+        // event 2 is tied to event 1 on the scope, so inherits its range
+        event_graph.add_time(2, 2, 250.0).unwrap();
+        event_graph.add_delay(1, 2, 2, 2, 0.0).unwrap();
+
+        let ranges = event_graph.solve_bounds().unwrap();
+        assert_eq!(ranges[&(1, 2)], Interval::new(100.0, 150.0));
+        assert_eq!(ranges[&(2, 1)], Interval::new(200.0, 200.0));
+    }
+
+    #[test]
+    fn one_sided_bound() {
+        // the scope must trigger at least 50 before current start
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 0.0).unwrap();
+        event_graph.add_bound(2, 2, 1, 1, 50.0, f64::INFINITY).unwrap();
+        let ranges = event_graph.solve_bounds().unwrap();
+        assert_eq!(ranges[&(2, 2)], Interval::unbounded());
+        let delay = event_graph.feasible_delay(1, Event::T0, 2, Event::Event(2)).unwrap();
+        assert!(delay.contains(-50.0));
+        assert!(!delay.contains(-49.0));
+        assert_eq!(delay.min, f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn infeasible_loop_is_reported() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 0.0).unwrap();
+        event_graph.add_time(1, 2, 100.0).unwrap();
+        event_graph.add_bound(1, 1, 1, 2, 10.0, 50.0).unwrap();
+        match event_graph.solve_bounds() {
+            Err(Errors::Infeasible(cycle)) => {
+                assert!(cycle.contains(&TimebaseEventKey::new(1, 1)));
+                assert!(cycle.contains(&TimebaseEventKey::new(1, 2)));
+            },
+            other => panic!("expected an infeasible loop, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_and_duplicate_bounds() {
+        let mut event_graph = DelayGraph::new();
+        assert!(event_graph.add_bound(1, 1, 2, 1, 10.0, 5.0).is_err());
+        event_graph.add_bound(1, 1, 2, 1, 5.0, 10.0).unwrap();
+        assert!(event_graph.add_bound(2, 1, 1, 1, 0.0, 1.0).is_err());
+        event_graph.remove_bound(2, 1, 1, 1).unwrap();
+        assert!(event_graph.remove_bound(1, 1, 2, 1).is_err());
+    }
+
+    #[test]
+    fn bound_looked_up_either_way_round() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_bound(2, 1, 1, 1, 5.0, f64::INFINITY).unwrap();
+        assert_eq!(event_graph.lookup_bound(2, 1, 1, 1), Some(Interval::new(5.0, f64::INFINITY)));
+        assert_eq!(event_graph.lookup_bound(1, 1, 2, 1), Some(Interval::new(f64::NEG_INFINITY, -5.0)));
+        assert_eq!(event_graph.lookup_bound(1, 1, 3, 1), None);
+    }
+}
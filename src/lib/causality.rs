@@ -0,0 +1,172 @@
+use crate::{DelayGraph, Errors, TimebaseEventKey};
+
+/// A physical link whose delay comes out negative, so that information
+/// would arrive before it was sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CausalityViolation {
+    pub from: TimebaseEventKey,
+    pub to: TimebaseEventKey,
+    pub delay: f64,
+    /// Nodes the delay was found through, just `[from, to]` when it was entered directly.
+    pub path: Vec<TimebaseEventKey>,
+}
+
+impl DelayGraph {
+
+    /// Mark the link from `event_1` on `timebase_1` to `event_2` on `timebase_2` as physical
+    /// propagation, such as a cable or a light path, so its delay can't be negative.
+    /// The delay itself may be entered or derived.
+    pub fn mark_physical(&mut self, timebase_1: usize, event_1: usize, timebase_2: usize, event_2: usize) -> Result<(), Errors> {
+        let key_1 = TimebaseEventKey::new(timebase_1, event_1);
+        let key_2 = TimebaseEventKey::new(timebase_2, event_2);
+        if self.physical.contains(&(key_2, key_1)) {
+            // both directions non-negative only allows zero, which is never what was meant
+            return Err(Errors::InvalidBound)
+        }
+        if !self.physical.insert((key_1, key_2)) {
+            return Err(Errors::AlreadyExists)
+        }
+        Ok(())
+    }
+
+    pub fn unmark_physical(&mut self, timebase_1: usize, event_1: usize, timebase_2: usize, event_2: usize) -> Result<(), Errors> {
+        let key_1 = TimebaseEventKey::new(timebase_1, event_1);
+        let key_2 = TimebaseEventKey::new(timebase_2, event_2);
+        if self.physical.remove(&(key_1, key_2)) {
+            Ok(())
+        } else {
+            Err(Errors::NotFound)
+        }
+    }
+
+    pub fn is_physical(&self, timebase_1: usize, event_1: usize, timebase_2: usize, event_2: usize) -> bool {
+        let key_1 = TimebaseEventKey::new(timebase_1, event_1);
+        let key_2 = TimebaseEventKey::new(timebase_2, event_2);
+        self.physical.contains(&(key_1, key_2))
+    }
+
+    /// Add a delay and mark it as physical propagation in the stated direction,
+    /// or if either can't be done, neither.
+    pub fn add_physical_delay(&mut self, timebase_1: usize, event_1: usize, timebase_2: usize, event_2: usize, delay: f64) -> Result<(), Errors> {
+        let key_1 = TimebaseEventKey::new(timebase_1, event_1);
+        let key_2 = TimebaseEventKey::new(timebase_2, event_2);
+        if delay.is_nan() || delay < 0.0 || self.physical.contains(&(key_2, key_1)) {
+            return Err(Errors::InvalidBound)
+        }
+        if self.physical.contains(&(key_1, key_2)) {
+            return Err(Errors::AlreadyExists)
+        }
+        self.add_delay(timebase_1, event_1, timebase_2, event_2, delay)?;
+        self.physical.insert((key_1, key_2));
+        Ok(())
+    }
+
+    /// Every physical link whose entered or derived delay is negative.
+    pub fn check_causality(&self) -> Vec<CausalityViolation> {
+        let mut violations = Vec::new();
+        for &(from, to) in &self.physical {
+            let path = if self.graph.contains_edge(from, to) {
+                vec![from, to]
+            } else if let Some(path) = self.path(from.timebase, from.event, to.timebase, to.event) {
+                path
+            } else {
+                continue
+            };
+            let delay = self.path_delay(&path);
+            if delay < 0.0 {
+                violations.push(CausalityViolation {from, to, delay, path});
+            }
+        }
+        violations
+    }
+
+    /// Physical links as constraints `to - from >= 0`, for use with the bounds solver.
+    pub(crate) fn physical_edges(&self) -> impl Iterator<Item = (TimebaseEventKey, TimebaseEventKey, f64)> + '_ {
+        self.physical.iter().map(|&(from, to)| (to, from, 0.0))
+    }
+}
+
+impl CausalityViolation {
+    /// Human readable description of the offending path.
+    pub fn describe(&self) -> String {
+        let nodes: Vec<String> = self.path.iter().map(|key| key.to_string()).collect();
+        format!("delay {} along {}", self.delay, nodes.join(" -> "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Event;
+
+    #[test]
+    fn backwards_cable() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_delay(1, 1, 2, 1, -65.5).unwrap();
+        event_graph.mark_physical(1, 1, 2, 1).unwrap();
+        let violations = event_graph.check_causality();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].delay, -65.5);
+        assert_eq!(violations[0].path, vec![TimebaseEventKey::new(1, 1), TimebaseEventKey::new(2, 1)]);
+        assert!(event_graph.add_physical_delay(1, 2, 2, 2, -1.0).is_err());
+    }
+
+    #[test]
+    fn physical_delay_added_whole_or_not_at_all() {
+        let mut event_graph = DelayGraph::new();
+        assert!(matches!(event_graph.add_physical_delay(1, 1, 2, 1, f64::NAN), Err(Errors::InvalidBound)));
+        event_graph.mark_physical(2, 1, 1, 1).unwrap();
+        assert!(matches!(event_graph.add_physical_delay(1, 1, 2, 1, 5.0), Err(Errors::InvalidBound)));
+        event_graph.mark_physical(1, 2, 2, 2).unwrap();
+        assert!(matches!(event_graph.add_physical_delay(1, 2, 2, 2, 5.0), Err(Errors::AlreadyExists)));
+        assert!(event_graph.delays().is_empty());
+
+        event_graph.add_physical_delay(1, 3, 2, 3, 5.0).unwrap();
+        assert!(event_graph.is_physical(1, 3, 2, 3));
+        assert_eq!(event_graph.get_delay(1, 3, 2, 3), Some(5.0));
+    }
+
+    #[test]
+    fn mark_removed_with_its_delay() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_physical_delay(1, 1, 2, 1, 5.0).unwrap();
+        event_graph.remove_delay(1, 1, 2, 1).unwrap();
+        event_graph.add_delay(1, 1, 2, 1, -5.0).unwrap();
+        assert!(!event_graph.is_physical(1, 1, 2, 1));
+        assert!(event_graph.check_causality().is_empty());
+    }
+
+    #[test]
+    ///  0   100
+    ///  |----|--->  experiment
+    ///   \    \ physical, derived as -50
+    ///    |----|--->  scope
+    ///   20    70
+    fn detector_sees_event_before_it_happened() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 0.0).unwrap();
+        event_graph.add_time(1, 2, 100.0).unwrap();
+        event_graph.add_time(2, 1, 20.0).unwrap();
+        event_graph.add_time(2, 2, 70.0).unwrap();
+        event_graph.add_physical_delay(1, 1, 2, 1, 0.0).unwrap();
+        event_graph.mark_physical(1, 2, 2, 2).unwrap();
+
+        let violations = event_graph.check_causality();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].delay, -50.0);
+        assert_eq!(violations[0].path.first(), Some(&TimebaseEventKey::new(1, 2)));
+        assert_eq!(violations[0].path.last(), Some(&TimebaseEventKey::new(2, 2)));
+        assert_eq!(violations[0].path.len(), 6);
+    }
+
+    #[test]
+    fn physical_links_limit_feasible_ranges() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 0.0).unwrap();
+        event_graph.mark_physical(1, 1, 2, 1).unwrap();
+        event_graph.add_bound(1, 1, 2, 1, -100.0, 100.0).unwrap();
+        let delay = event_graph.feasible_delay(1, Event::Event(1), 2, Event::Event(1)).unwrap();
+        assert_eq!(delay.min, 0.0);
+        assert_eq!(delay.max, 100.0);
+    }
+}
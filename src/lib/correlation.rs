@@ -0,0 +1,162 @@
+use crate::monte_carlo::Distribution;
+use crate::waveform::{grid, Waveform};
+use crate::{DelayGraph, Errors};
+
+/// Offset between two recordings of the same signal, each on the clock of its own timebase.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DelayEstimate {
+    pub from: usize,
+    pub to: usize,
+    /// How much later the signal appears on `to` than on `from`, in their own times.
+    pub delay: f64,
+    /// Estimated standard deviation of `delay`.
+    pub uncertainty: f64,
+    /// Correlation coefficient at the best offset, 1 for identical shapes.
+    pub correlation: f64,
+}
+
+impl DelayEstimate {
+    /// Enter the measurement as the delay of `event`, seen in both recordings, from one timebase
+    /// to the other, with the uncertainty of the estimate.
+    pub fn add_to_graph(&self, graph: &mut DelayGraph, event: usize) -> Result<(), Errors> {
+        let distribution = Distribution::Normal { std_dev: self.uncertainty };
+        if !distribution.is_valid() {
+            return Err(Errors::InvalidBound)
+        }
+        graph.add_delay(self.from, event, self.to, event, self.delay)?;
+        graph.set_distribution(self.from, event, self.to, event, distribution)
+    }
+}
+
+/// Resample `channel` onto a grid of spacing `dt` over its own recording, less its mean.
+fn on_grid(waveform: &Waveform, channel: &str, dt: f64) -> Option<(f64, Vec<f64>)> {
+    let (first, last) = (waveform.time.first()?, waveform.time.last()?);
+    waveform.channel(channel)?;
+    let grid = grid(first, last, dt)?;
+    let resampled = waveform.resample(&grid);
+    let mut values = resampled.channel(channel)?.values.clone();
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    values.iter_mut().for_each(|value| *value -= mean);
+    Some((first, values))
+}
+
+/// Correlation coefficient of `a` against `b` moved `lag` samples later, and the samples it covers.
+fn coefficient(a: &[f64], b: &[f64], lag: isize) -> (f64, usize) {
+    let start = 0.max(-lag) as usize;
+    let end = a.len().min((b.len() as isize - lag).max(0) as usize);
+    let n = end.saturating_sub(start);
+    let b_start = (start as isize + lag) as usize;
+    let (mut ab, mut aa, mut bb) = (0.0, 0.0, 0.0);
+    for (x, y) in a[start..start + n].iter().zip(&b[b_start..b_start + n]) {
+        ab += x * y;
+        aa += x * x;
+        bb += y * y;
+    }
+    if aa == 0.0 || bb == 0.0 { (0.0, n) } else { (ab / (aa * bb).sqrt(), n) }
+}
+
+/// Estimate how much later `second_channel` of `second` shows the signal seen on `first_channel` of `first`.
+///
+/// Both are resampled every `dt`, offsets overlapping at least half of the shorter recording
+/// are tried, and the best is refined with a parabola through its neighbours. The uncertainty
+/// is the Cramér-Rao bound for the signal to noise ratio implied by the correlation coefficient.
+pub fn cross_correlate(first: &Waveform, first_channel: &str, second: &Waveform, second_channel: &str, dt: f64) -> Option<DelayEstimate> {
+    if dt.is_nan() || dt <= 0.0 {
+        return None
+    }
+    let (start_a, a) = on_grid(first, first_channel, dt)?;
+    let (start_b, b) = on_grid(second, second_channel, dt)?;
+    let min_overlap = a.len().min(b.len()).div_ceil(2);
+
+    let lags = -(b.len() as isize - 1)..a.len() as isize;
+    let coefficients: Vec<(isize, f64, usize)> = lags
+        .map(|lag| {
+            let (c, n) = coefficient(&a, &b, -lag);
+            (lag, c, n)
+        })
+        .filter(|&(_, _, n)| n >= min_overlap)
+        .collect();
+    let best = (0..coefficients.len()).reduce(|best, i| if coefficients[i].1 > coefficients[best].1 { i } else { best })?;
+    let (lag, correlation, n) = coefficients[best];
+    if correlation <= 0.0 {
+        return None
+    }
+
+    let shift = if best > 0 && best + 1 < coefficients.len() {
+        let (before, after) = (coefficients[best - 1].1, coefficients[best + 1].1);
+        let curvature = before - 2.0 * correlation + after;
+        if curvature < 0.0 { 0.5 * (before - after) / curvature } else { 0.0 }
+    } else {
+        0.0
+    };
+    // sample i of the second recording lines up with sample i + lag of the first
+    let delay = start_b - start_a - (lag as f64 + shift) * dt;
+
+    // mean square angular frequency of the signal
+    let power = a.iter().map(|value| value * value).sum::<f64>();
+    let slope_power = a.windows(2).map(|pair| ((pair[1] - pair[0]) / dt).powi(2)).sum::<f64>();
+    let bandwidth = slope_power / power;
+    let rho = correlation.min(1.0);
+    let uncertainty = ((1.0 - rho * rho) / (n as f64 * rho * rho * bandwidth)).sqrt();
+
+    Some(DelayEstimate {from: first.timebase, to: second.timebase, delay, uncertainty, correlation})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::waveform::{Channel, TimeAxis};
+
+    fn gaussian(timebase: usize, time: TimeAxis, centre: f64, noise: f64) -> Waveform {
+        let values = time.times().iter().enumerate().map(|(i, t)| {
+            // repeatable stand-in for noise
+            let wobble = ((i as f64 * 12.9898).sin() * 43758.5453).fract();
+            (-((t - centre) / 5.0).powi(2) / 2.0).exp() + noise * wobble
+        }).collect();
+        Waveform::new(timebase, time, vec![Channel {name: "sib mon".to_owned(), values}]).unwrap()
+    }
+
+    #[test]
+    fn offset_between_scopes() {
+        // head scope samples every 1 from 0, shimadzu every 0.5 from 2500
+        let head = gaussian(1, TimeAxis::Uniform {t0: 0.0, dt: 1.0, len: 100}, 50.0, 0.0);
+        let shimadzu = gaussian(2, TimeAxis::Uniform {t0: 2500.0, dt: 0.5, len: 200}, 2573.3, 0.0);
+        let estimate = cross_correlate(&head, "sib mon", &shimadzu, "sib mon", 0.5).unwrap();
+        assert_eq!((estimate.from, estimate.to), (1, 2));
+        assert!((estimate.delay - 2523.3).abs() < 0.05);
+        assert!(estimate.correlation > 0.99);
+        assert!(estimate.uncertainty < 0.05);
+
+        let back = cross_correlate(&shimadzu, "sib mon", &head, "sib mon", 0.5).unwrap();
+        assert!((back.delay + 2523.3).abs() < 0.05);
+        assert!(cross_correlate(&head, "ak mon", &shimadzu, "sib mon", 0.5).is_none());
+        for dt in [0.0, -0.5, f64::NAN] {
+            assert!(cross_correlate(&head, "sib mon", &shimadzu, "sib mon", dt).is_none());
+        }
+    }
+
+    #[test]
+    fn noise_widens_the_estimate() {
+        let head = gaussian(1, TimeAxis::Uniform {t0: 0.0, dt: 1.0, len: 100}, 50.0, 0.0);
+        let quiet = gaussian(2, TimeAxis::Uniform {t0: 0.0, dt: 1.0, len: 100}, 42.0, 0.0);
+        let noisy = gaussian(2, TimeAxis::Uniform {t0: 0.0, dt: 1.0, len: 100}, 42.0, 0.3);
+        let quiet = cross_correlate(&head, "sib mon", &quiet, "sib mon", 1.0).unwrap();
+        let noisy = cross_correlate(&head, "sib mon", &noisy, "sib mon", 1.0).unwrap();
+        assert!(noisy.uncertainty > quiet.uncertainty);
+        assert!((noisy.delay + 8.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn recorded_in_the_graph() {
+        let estimate = DelayEstimate {from: 1, to: 2, delay: 2523.3, uncertainty: 0.1, correlation: 0.98};
+        let mut event_graph = DelayGraph::new();
+        let unsure = DelayEstimate {uncertainty: f64::NAN, ..estimate};
+        assert!(matches!(unsure.add_to_graph(&mut event_graph, 4), Err(Errors::InvalidBound)));
+        assert!(event_graph.delays().is_empty());
+
+        // no times are needed on either timebase
+        estimate.add_to_graph(&mut event_graph, 4).unwrap();
+        assert_eq!(event_graph.get_delay(1, 4, 2, 4), Some(2523.3));
+        assert!(estimate.add_to_graph(&mut event_graph, 4).is_err());
+    }
+}
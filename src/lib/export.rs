@@ -0,0 +1,266 @@
+//! Writers handing waveforms on to Python users: NumPy `.npy`/`.npz` and CSV with a provenance header.
+
+use crate::waveform::Waveform;
+use crate::{Errors, Event};
+
+/// One dimensional little endian `float64` array in NumPy `.npy` format, version 1.0.
+pub fn npy(values: &[f64]) -> Vec<u8> {
+    let mut header = format!("{{'descr': '<f8', 'fortran_order': False, 'shape': ({},), }}", values.len());
+    // magic, version and header length take 10 bytes, and the whole header is padded to 64
+    let padding = 63 - (10 + header.len()) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+    bytes.extend((header.len() as u16).to_le_bytes());
+    bytes.extend(header.as_bytes());
+    for value in values {
+        bytes.extend(value.to_le_bytes());
+    }
+    bytes
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Zip archive of uncompressed files, which is all `numpy.load` needs of an `.npz`.
+fn zip(files: &[(String, Vec<u8>)]) -> Vec<u8> {
+    // 1980-01-01 00:00, the earliest date zip can hold
+    let (time, date) = (0u16, 0x21u16);
+    let mut archive = Vec::new();
+    let mut directory = Vec::new();
+    for (name, contents) in files {
+        let offset = archive.len() as u32;
+        let crc = crc32(contents);
+        let size = contents.len() as u32;
+        let common = |record: &mut Vec<u8>| {
+            record.extend(20u16.to_le_bytes()); // version needed
+            record.extend(0u16.to_le_bytes()); // flags
+            record.extend(0u16.to_le_bytes()); // stored
+            record.extend(time.to_le_bytes());
+            record.extend(date.to_le_bytes());
+            record.extend(crc.to_le_bytes());
+            record.extend(size.to_le_bytes());
+            record.extend(size.to_le_bytes());
+            record.extend((name.len() as u16).to_le_bytes());
+            record.extend(0u16.to_le_bytes()); // extra field length
+        };
+
+        archive.extend(0x0403_4b50u32.to_le_bytes());
+        common(&mut archive);
+        archive.extend(name.as_bytes());
+        archive.extend(contents);
+
+        directory.extend(0x0201_4b50u32.to_le_bytes());
+        directory.extend(20u16.to_le_bytes()); // version made by
+        common(&mut directory);
+        directory.extend(0u16.to_le_bytes()); // comment length
+        directory.extend(0u16.to_le_bytes()); // disk number
+        directory.extend(0u16.to_le_bytes()); // internal attributes
+        directory.extend(0u32.to_le_bytes()); // external attributes
+        directory.extend(offset.to_le_bytes());
+        directory.extend(name.as_bytes());
+    }
+
+    let directory_offset = archive.len() as u32;
+    let directory_size = directory.len() as u32;
+    archive.extend(directory);
+    archive.extend(0x0605_4b50u32.to_le_bytes());
+    archive.extend(0u16.to_le_bytes()); // this disk
+    archive.extend(0u16.to_le_bytes()); // disk with the directory
+    archive.extend((files.len() as u16).to_le_bytes());
+    archive.extend((files.len() as u16).to_le_bytes());
+    archive.extend(directory_size.to_le_bytes());
+    archive.extend(directory_offset.to_le_bytes());
+    archive.extend(0u16.to_le_bytes()); // comment length
+    archive
+}
+
+/// `name` made safe to use as a file in an archive, with no directories or parent references.
+fn entry_name(name: &str) -> String {
+    let name: String = name.chars().map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c }).collect();
+    match name.as_str() {
+        "" | "." | ".." => format!("_{}", name),
+        _ => name,
+    }
+}
+
+/// The timebase all `waveforms` are on, once every channel name, as written by `name`,
+/// is checked to be unique and not any of `reserved`.
+fn shared_timebase(waveforms: &[Waveform], name: fn(&str) -> String, reserved: &[&str]) -> Result<usize, Errors> {
+    let first = waveforms.first().ok_or(Errors::NotFound)?;
+    if waveforms.iter().any(|waveform| waveform.timebase != first.timebase) {
+        return Err(Errors::TimebaseMismatch)
+    }
+    let mut names: Vec<String> = waveforms.iter()
+        .flat_map(|waveform| waveform.channels.iter().map(|channel| name(&channel.name)))
+        .collect();
+    names.sort();
+    if names.windows(2).any(|pair| pair[0] == pair[1]) || names.iter().any(|name| reserved.contains(&name.as_str())) {
+        return Err(Errors::AlreadyExists)
+    }
+    Ok(first.timebase)
+}
+
+/// `.npz` archive with a `time` array and one array per channel, named after the channel
+/// with any `/` or `\` replaced so it can't reach outside the archive.
+/// The waveforms must be on one timebase and one time axis, see [`crate::waveform::shared_grid`].
+pub fn npz(waveforms: &[Waveform]) -> Result<Vec<u8>, Errors> {
+    shared_timebase(waveforms, entry_name, &["time"])?;
+    let time = &waveforms[0].time;
+    if waveforms.iter().any(|waveform| waveform.time != *time) {
+        return Err(Errors::LengthMismatch)
+    }
+    let mut files = vec![("time.npy".to_owned(), npy(&time.times()))];
+    for channel in waveforms.iter().flat_map(|waveform| &waveform.channels) {
+        files.push((format!("{}.npy", entry_name(&channel.name)), npy(&channel.values)));
+    }
+    Ok(zip(&files))
+}
+
+/// Comment lines saying where each waveform came from and how it was moved onto its timebase.
+pub fn provenance(waveforms: &[Waveform], graph: &str) -> Vec<String> {
+    let mut lines = vec![format!("graph: {}", graph)];
+    for waveform in waveforms {
+        let names: Vec<&str> = waveform.channels.iter().map(|channel| channel.name.as_str()).collect();
+        lines.push(format!("channels {}:", names.join(", ")));
+        for (key, value) in &waveform.metadata {
+            lines.push(format!("  {}: {}", key, value));
+        }
+        for resync in &waveform.history {
+            let aligned_on = match resync.event {
+                Event::Event(event) => format!("event {}", event),
+                Event::T0 => "T0".to_owned(),
+            };
+            let path: Vec<String> = resync.path.iter().map(|key| key.to_string()).collect();
+            lines.push(format!(
+                "  timebase {} to {} on {}, offset {}, via {}",
+                resync.from, resync.to, aligned_on, resync.offset, path.join(" -> "),
+            ));
+        }
+    }
+    lines
+}
+
+/// Tidy CSV with a row per sample, as `timebase, channel, time, value`, after a `#` commented
+/// provenance header naming `graph`, e.g. the file the delays were loaded from.
+/// The waveforms must be on one timebase, see [`Waveform::resync`], but may be sampled at different times.
+pub fn csv(waveforms: &[Waveform], graph: &str) -> Result<String, Errors> {
+    let timebase = shared_timebase(waveforms, str::to_owned, &[])?.to_string();
+    let mut output = String::new();
+    for line in provenance(waveforms, graph) {
+        output.push_str(&format!("# {}\n", line));
+    }
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(["timebase", "channel", "time", "value"]).unwrap();
+    for waveform in waveforms {
+        let times = waveform.time.times();
+        for channel in &waveform.channels {
+            for (t, value) in times.iter().zip(&channel.values) {
+                writer.write_record([timebase.as_str(), channel.name.as_str(), &t.to_string(), &value.to_string()]).unwrap();
+            }
+        }
+    }
+    output.push_str(&String::from_utf8(writer.into_inner().unwrap()).unwrap());
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::waveform::{Channel, TimeAxis};
+    use crate::DelayGraph;
+
+    fn resynced() -> Vec<Waveform> {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 0.0).unwrap();
+        event_graph.add_time(2, 1, 2500.0).unwrap();
+        event_graph.add_delay(1, 1, 2, 1, 65.5).unwrap();
+        let time = TimeAxis::Uniform {t0: 2490.0, dt: 5.0, len: 3};
+        let current = vec![Channel {name: "current".to_owned(), values: vec![0.0, 1.0, 2.0]}];
+        let xray = vec![Channel {name: "xray".to_owned(), values: vec![0.5, f64::NAN, 0.25]}];
+        let mut current = Waveform::new(2, time.clone(), current).unwrap();
+        current.metadata.insert("Model".to_owned(), "DPO4104".to_owned());
+        let xray = Waveform::new(2, time, xray).unwrap();
+        [current, xray].iter().map(|waveform| waveform.resync(&event_graph, 1, Event::Event(1)).unwrap()).collect()
+    }
+
+    #[test]
+    fn npy_header() {
+        let bytes = npy(&[1.0, 2.0]);
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+        assert!(header.starts_with("{'descr': '<f8', 'fortran_order': False, 'shape': (2,), }"));
+        assert!(header.ends_with(" \n"));
+        assert_eq!(&bytes[10 + header_len..], [1.0f64.to_le_bytes(), 2.0f64.to_le_bytes()].concat());
+    }
+
+    #[test]
+    fn npz_archive() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        let archive = npz(&resynced()).unwrap();
+        assert_eq!(&archive[..4], b"PK\x03\x04");
+        let end = archive.len() - 22;
+        assert_eq!(&archive[end..end + 4], b"PK\x05\x06");
+        assert_eq!(u16::from_le_bytes([archive[end + 10], archive[end + 11]]), 3);
+        let text = String::from_utf8_lossy(&archive);
+        assert!(text.contains("time.npy") && text.contains("current.npy") && text.contains("xray.npy"));
+    }
+
+    #[test]
+    fn csv_with_provenance() {
+        let csv = csv(&resynced(), "cepage_delays.csv").unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "# graph: cepage_delays.csv");
+        assert_eq!(lines[1], "# channels current:");
+        assert_eq!(lines[2], "#   Model: DPO4104");
+        assert_eq!(lines[3], "#   timebase 2 to 1 on event 1, offset -2500, via (2, 1) -> (1, 1)");
+        assert_eq!(lines[6], "timebase,channel,time,value");
+        assert_eq!(lines[7], "1,current,-10,0");
+        assert_eq!(lines[10], "1,xray,-10,0.5");
+        assert_eq!(lines[11], "1,xray,-5,NaN");
+        assert_eq!(lines.len(), 13);
+    }
+
+    #[test]
+    fn entries_stay_in_the_archive() {
+        assert_eq!(entry_name("../../etc/passwd"), ".._.._etc_passwd");
+        assert_eq!(entry_name("C:\\temp"), "C:_temp");
+        assert_eq!(entry_name(".."), "_..");
+        let mut waveforms = resynced();
+        waveforms[0].channels[0].name = "../current".to_owned();
+        let text = String::from_utf8_lossy(&npz(&waveforms).unwrap()).into_owned();
+        assert!(text.contains(".._current.npy") && !text.contains("../"));
+        // different names that would be written as the same entry
+        waveforms[1].channels[0].name = ".._current".to_owned();
+        assert!(matches!(npz(&waveforms), Err(Errors::AlreadyExists)));
+        assert!(csv(&waveforms, "").is_ok());
+    }
+
+    #[test]
+    fn waveforms_must_line_up() {
+        let mut waveforms = resynced();
+        assert!(npz(&[]).is_err());
+        waveforms[1].channels[0].name = "current".to_owned();
+        assert!(matches!(csv(&waveforms, ""), Err(Errors::AlreadyExists)));
+        waveforms[1].channels[0].name = "xray".to_owned();
+        waveforms[1].time = TimeAxis::Explicit(vec![0.0, 1.0, 2.0]);
+        assert!(matches!(npz(&waveforms), Err(Errors::LengthMismatch)));
+        // long rows don't need one time axis, only one timebase
+        assert!(csv(&waveforms, "").is_ok());
+        waveforms[1].timebase = 2;
+        assert!(matches!(csv(&waveforms, ""), Err(Errors::TimebaseMismatch)));
+        assert!(matches!(npz(&waveforms), Err(Errors::TimebaseMismatch)));
+    }
+}
@@ -0,0 +1,231 @@
+use crate::monte_carlo::Distribution;
+use crate::waveform::{interpolate, Waveform};
+use crate::{DelayGraph, Errors};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slope {
+    Rising,
+    Falling,
+}
+
+/// How an event time is picked off a trace.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    /// First crossing of `level` after the signal has been at least
+    /// `hysteresis` on the other side of it, so noise about the level does not fire.
+    Threshold { level: f64, slope: Slope, hysteresis: f64 },
+    /// Zero crossing of `fraction` of the signal less the signal `delay` earlier,
+    /// which for a fixed pulse shape does not move with the amplitude.
+    ConstantFraction { fraction: f64, delay: f64 },
+    /// Maximum, refined with a parabola through the highest sample and its neighbours.
+    Peak,
+    /// Leading edge crossing halfway from the baseline to the peak.
+    HalfRise,
+}
+
+/// An event time found on a trace, on the timebase of the waveform.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fiducial {
+    pub timebase: usize,
+    pub time: f64,
+    /// Estimated standard deviation of `time`.
+    pub uncertainty: f64,
+}
+
+impl Fiducial {
+    /// Enter the time as `event` on the timebase of the waveform, with its uncertainty
+    /// as a normal distribution for Monte Carlo sampling.
+    pub fn add_to_graph(&self, graph: &mut DelayGraph, event: usize) -> Result<(), Errors> {
+        let distribution = Distribution::Normal { std_dev: self.uncertainty };
+        if !distribution.is_valid() {
+            return Err(Errors::InvalidBound)
+        }
+        graph.add_time(self.timebase, event, self.time)?;
+        graph.set_time_distribution(self.timebase, event, distribution)
+    }
+}
+
+/// Finds one fiducial per trace, estimating the noise from the first `baseline` samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Extractor {
+    pub method: Method,
+    pub baseline: usize,
+}
+
+impl Extractor {
+    pub fn new(method: Method) -> Self {
+        Self {method, baseline: 16}
+    }
+
+    pub fn with_baseline(self, baseline: usize) -> Self {
+        Self {baseline, ..self}
+    }
+
+    /// Time of the fiducial on channel `channel` of `waveform`, `None` if the trace never shows it.
+    pub fn extract(&self, waveform: &Waveform, channel: &str) -> Option<Fiducial> {
+        let values = &waveform.channel(channel)?.values;
+        let times = waveform.time.times();
+        let baseline = &values[..self.baseline.min(values.len())];
+        let noise = std_dev(baseline);
+        let level = if baseline.is_empty() { values.first().copied()? } else { mean(baseline) };
+
+        let (time, uncertainty) = match self.method {
+            Method::Threshold { level, slope, hysteresis } => {
+                let sign = if slope == Slope::Rising { 1.0 } else { -1.0 };
+                let mut armed = false;
+                let mut found = None;
+                for i in 0..values.len() {
+                    let value = sign * (values[i] - level);
+                    if value <= -hysteresis.abs() {
+                        armed = true;
+                    } else if armed && value >= 0.0 && i > 0 {
+                        found = Some(crossing(&times, values, i, level, noise));
+                        break
+                    }
+                }
+                found?
+            },
+            Method::ConstantFraction { fraction, delay } => {
+                let shaped: Vec<f64> = times.iter().zip(values).map(|(&t, &value)| {
+                    let delayed = interpolate(&times, values, t - delay);
+                    fraction * (value - level) - if delayed.is_nan() { 0.0 } else { delayed - level }
+                }).collect();
+                // the shaped pulse swings positive first, then crosses zero
+                let top = argmax(&shaped)?;
+                let i = (top + 1..shaped.len()).find(|&i| shaped[i] <= 0.0)?;
+                let (time, _) = crossing(&times, &shaped, i, 0.0, 0.0);
+                let slope = (shaped[i] - shaped[i - 1]) / (times[i] - times[i - 1]);
+                // noise enters both the prompt and the delayed signal
+                (time, noise * (1.0 + fraction * fraction).sqrt() / slope.abs())
+            },
+            Method::Peak => {
+                let top = argmax(values)?;
+                let spacing = if top + 1 < times.len() { times[top + 1] - times[top] } else if top > 0 { times[top] - times[top - 1] } else { 0.0 };
+                let time = if top > 0 && top + 1 < values.len() {
+                    vertex(&times[top - 1..=top + 1], &values[top - 1..=top + 1])
+                } else {
+                    times[top]
+                };
+                // a flat top can sit anywhere within the sample spacing
+                (time, spacing / 12f64.sqrt())
+            },
+            Method::HalfRise => {
+                let top = argmax(values)?;
+                let half = level + 0.5 * (values[top] - level);
+                let i = (1..=top).rev().find(|&i| values[i - 1] < half)?;
+                crossing(&times, values, i, half, noise)
+            },
+        };
+        Some(Fiducial {timebase: waveform.timebase, time, uncertainty})
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn std_dev(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0
+    }
+    let mean = mean(values);
+    (values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64).sqrt()
+}
+
+fn argmax(values: &[f64]) -> Option<usize> {
+    (0..values.len()).filter(|&i| !values[i].is_nan()).reduce(|best, i| if values[i] > values[best] { i } else { best })
+}
+
+/// Time `level` is crossed between samples `i - 1` and `i`, and its spread from the noise on the slope.
+fn crossing(times: &[f64], values: &[f64], i: usize, level: f64, noise: f64) -> (f64, f64) {
+    let (t_1, t_2) = (times[i - 1], times[i]);
+    let (v_1, v_2) = (values[i - 1], values[i]);
+    if v_1 == v_2 {
+        return (t_2, 0.0)
+    }
+    let slope = (v_2 - v_1) / (t_2 - t_1);
+    (t_1 + (level - v_1) / slope, noise / slope.abs())
+}
+
+/// Time of the top of the parabola through three points.
+fn vertex(times: &[f64], values: &[f64]) -> f64 {
+    let (t_0, t_1, t_2) = (times[0], times[1], times[2]);
+    let (y_0, y_1, y_2) = (values[0], values[1], values[2]);
+    let numerator = (t_1 - t_0).powi(2) * (y_1 - y_2) - (t_1 - t_2).powi(2) * (y_1 - y_0);
+    let denominator = (t_1 - t_0) * (y_1 - y_2) - (t_1 - t_2) * (y_1 - y_0);
+    if denominator == 0.0 { t_1 } else { t_1 - 0.5 * numerator / denominator }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::waveform::{Channel, TimeAxis};
+
+    /// flat at 1 until 10, rising by `amplitude` per unit time to 1 + 10 * amplitude at 20, then back down
+    fn pulse(amplitude: f64) -> Waveform {
+        let values = (0..40).map(|t| {
+            let t = t as f64;
+            1.0 + amplitude * if t < 10.0 { 0.0 } else if t < 20.0 { t - 10.0 } else { (30.0 - t).max(0.0) }
+        }).collect();
+        let time = TimeAxis::Uniform {t0: 100.0, dt: 1.0, len: 40};
+        Waveform::new(2, time, vec![Channel {name: "xray".to_owned(), values}]).unwrap()
+    }
+
+    #[test]
+    fn edges_and_peak() {
+        let waveform = pulse(1.0);
+        let rise = Extractor::new(Method::HalfRise).with_baseline(5).extract(&waveform, "xray").unwrap();
+        assert_eq!(rise.time, 115.0);
+        assert_eq!(rise.uncertainty, 0.0);
+
+        let peak = Extractor::new(Method::Peak).extract(&waveform, "xray").unwrap();
+        assert_eq!(peak.time, 120.0);
+
+        let falling = Method::Threshold { level: 4.0, slope: Slope::Falling, hysteresis: 0.5 };
+        let fall = Extractor::new(falling).extract(&waveform, "xray").unwrap();
+        assert_eq!(fall.time, 127.0);
+        assert!(Extractor::new(Method::Peak).extract(&waveform, "current").is_none());
+    }
+
+    #[test]
+    fn hysteresis_ignores_noise_at_the_start() {
+        let mut waveform = pulse(1.0);
+        // starts above the level, so the first crossing only counts after dropping well below it
+        waveform.channels[0].values[0] = 3.0;
+        let rising = Method::Threshold { level: 2.5, slope: Slope::Rising, hysteresis: 1.0 };
+        let fiducial = Extractor::new(rising).with_baseline(0).extract(&waveform, "xray").unwrap();
+        assert_eq!(fiducial.time, 111.5);
+        let never = Method::Threshold { level: 20.0, slope: Slope::Rising, hysteresis: 1.0 };
+        assert!(Extractor::new(never).extract(&waveform, "xray").is_none());
+    }
+
+    #[test]
+    fn constant_fraction_ignores_amplitude() {
+        let cfd = Extractor::new(Method::ConstantFraction { fraction: 0.5, delay: 2.0 }).with_baseline(5);
+        let small = cfd.extract(&pulse(1.0), "xray").unwrap();
+        let large = cfd.extract(&pulse(3.0), "xray").unwrap();
+        // 0.5 (t - 10) = t - 12 at t = 14
+        assert!((small.time - 114.0).abs() < 1e-12);
+        assert!((large.time - 114.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn noise_sets_the_uncertainty() {
+        let mut waveform = pulse(1.0);
+        for (i, value) in waveform.channels[0].values[..10].iter_mut().enumerate() {
+            *value += if i % 2 == 0 { 0.1 } else { -0.1 };
+        }
+        let fiducial = Extractor::new(Method::HalfRise).with_baseline(10).extract(&waveform, "xray").unwrap();
+        assert!((fiducial.uncertainty - 0.1 * (10.0f64 / 9.0).sqrt()).abs() < 1e-12);
+
+        let mut event_graph = DelayGraph::new();
+        for uncertainty in [f64::NAN, -0.1] {
+            let unsure = Fiducial {uncertainty, ..fiducial};
+            assert!(matches!(unsure.add_to_graph(&mut event_graph, 3), Err(Errors::InvalidBound)));
+            assert_eq!(event_graph.get_time(2, 3), None);
+        }
+        fiducial.add_to_graph(&mut event_graph, 3).unwrap();
+        assert_eq!(event_graph.get_time(2, 3), Some(fiducial.time));
+        assert!(fiducial.add_to_graph(&mut event_graph, 3).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use crate::planner::Plan;
+use crate::{DelayGraph, Errors, TimebaseEventKey};
+
+/// What a channel delay is measured from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reference {
+    /// The trigger input of the generator.
+    T0,
+    /// Another channel of the same generator, by index, so that `B = A + x`.
+    Channel(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratorChannel {
+    pub name: String,
+    /// Event the channel output produces on the generator timebase.
+    pub event: usize,
+    pub reference: Reference,
+    /// Programmed delay relative to `reference`, a multiple of the resolution.
+    pub delay: f64,
+    /// Calibrated delay between the programmed time and the output edge.
+    pub insertion_delay: f64,
+}
+
+/// A delay pulse generator whose timebase starts when its trigger input arrives.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DelayGenerator {
+    pub timebase: usize,
+    /// Event the trigger input is recorded as, at time zero on the generator timebase.
+    pub trigger: usize,
+    /// Smallest step a delay can be set in.
+    pub resolution: f64,
+    pub channels: Vec<GeneratorChannel>,
+}
+
+impl DelayGenerator {
+    /// Generator setting delays in steps of `resolution`, which must be positive and finite.
+    pub fn new(timebase: usize, trigger: usize, resolution: f64) -> Result<Self, Errors> {
+        if !resolution.is_normal() || resolution < 0.0 {
+            return Err(Errors::InvalidBound)
+        }
+        Ok(Self {timebase, trigger, resolution, channels: Vec::new()})
+    }
+
+    pub fn round(&self, delay: f64) -> f64 {
+        (delay / self.resolution).round() * self.resolution
+    }
+
+    /// `delay` rounded to the resolution, if it still is a finite time.
+    fn rounded(&self, delay: f64) -> Result<f64, Errors> {
+        let delay = self.round(delay);
+        if delay.is_finite() { Ok(delay) } else { Err(Errors::InvalidBound) }
+    }
+
+    /// Add a channel and return its index. A channel may only reference earlier channels.
+    pub fn add_channel(&mut self, name: &str, event: usize, reference: Reference, delay: f64) -> Result<usize, Errors> {
+        if let Reference::Channel(index) = reference {
+            if index >= self.channels.len() {
+                return Err(Errors::NotFound)
+            }
+        }
+        if event == self.trigger || self.channels.iter().any(|channel| channel.event == event) {
+            return Err(Errors::AlreadyExists)
+        }
+        let delay = self.rounded(delay)?;
+        self.channels.push(GeneratorChannel {name: name.to_owned(), event, reference, delay, insertion_delay: 0.0});
+        Ok(self.channels.len() - 1)
+    }
+
+    pub fn set_delay(&mut self, channel: usize, delay: f64) -> Result<(), Errors> {
+        let delay = self.rounded(delay)?;
+        let channel = self.channels.get_mut(channel).ok_or(Errors::NotFound)?;
+        channel.delay = delay;
+        Ok(())
+    }
+
+    pub fn set_insertion_delay(&mut self, channel: usize, insertion_delay: f64) -> Result<(), Errors> {
+        if !insertion_delay.is_finite() {
+            return Err(Errors::InvalidBound)
+        }
+        let channel = self.channels.get_mut(channel).ok_or(Errors::NotFound)?;
+        channel.insertion_delay = insertion_delay;
+        Ok(())
+    }
+
+    /// Programmed delay of a channel from the trigger input, following any chain of references.
+    pub fn setting(&self, channel: usize) -> Option<f64> {
+        let channel = self.channels.get(channel)?;
+        match channel.reference {
+            Reference::T0 => Some(channel.delay),
+            Reference::Channel(index) => Some(self.setting(index)? + channel.delay),
+        }
+    }
+
+    /// Time of the channel output edge on the generator timebase.
+    pub fn output_time(&self, channel: usize) -> Option<f64> {
+        Some(self.setting(channel)? + self.channels[channel].insertion_delay)
+    }
+
+    /// Write the trigger input and every channel output into `graph` as times on the generator timebase.
+    pub fn apply(&self, graph: &mut DelayGraph) -> Result<(), Errors> {
+        graph.update_time(self.timebase, self.trigger, 0.0)?;
+        for (index, channel) in self.channels.iter().enumerate() {
+            graph.update_time(self.timebase, channel.event, self.output_time(index).unwrap())?;
+        }
+        Ok(())
+    }
+
+    /// Mark every channel output as adjustable in `graph` so it can be planned,
+    /// settable from its insertion delay up to `max_delay` after it.
+    pub fn add_channels_to(&self, graph: &mut DelayGraph, max_delay: f64) -> Result<(), Errors> {
+        for channel in &self.channels {
+            let min = channel.insertion_delay;
+            graph.add_channel(self.timebase, self.trigger, self.timebase, channel.event, min, min + max_delay)?;
+        }
+        Ok(())
+    }
+
+    /// Take channel settings from a plan made with [`DelayGenerator::add_channels_to`],
+    /// keeping each channel's reference and rounding to the resolution.
+    /// If any setting can't be taken none are.
+    pub fn set_from_plan(&mut self, plan: &Plan) -> Result<(), Errors> {
+        let trigger = TimebaseEventKey::new(self.timebase, self.trigger);
+        let mut planned = self.clone();
+        for index in 0..planned.channels.len() {
+            let output = TimebaseEventKey::new(planned.timebase, planned.channels[index].event);
+            let Some(setting) = plan.settings.iter().find(|setting| setting.from == trigger && setting.to == output) else {
+                continue
+            };
+            let channel = &planned.channels[index];
+            let reference_setting = match channel.reference {
+                Reference::T0 => 0.0,
+                Reference::Channel(reference) => planned.setting(reference).unwrap(),
+            };
+            planned.set_delay(index, setting.delay - channel.insertion_delay - reference_setting)?;
+        }
+        *self = planned;
+        Ok(())
+    }
+
+    /// Table of channel settings as CSV, in the same units as the graph.
+    pub fn settings_table(&self) -> String {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(["channel", "reference", "delay", "insertion delay", "output"]).unwrap();
+        for (index, channel) in self.channels.iter().enumerate() {
+            let reference = match channel.reference {
+                Reference::T0 => "T0".to_owned(),
+                Reference::Channel(reference) => self.channels[reference].name.clone(),
+            };
+            writer.write_record([
+                channel.name.clone(),
+                reference,
+                channel.delay.to_string(),
+                channel.insertion_delay.to_string(),
+                self.output_time(index).unwrap().to_string(),
+            ]).unwrap();
+        }
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planner::{ChannelSetting, Target};
+    use crate::bounds::Interval;
+
+    fn generator() -> DelayGenerator {
+        let mut generator = DelayGenerator::new(0, 0, 0.5).unwrap();
+        let a = generator.add_channel("A", 1, Reference::T0, 100.2).unwrap();
+        generator.add_channel("B", 2, Reference::Channel(a), 50.0).unwrap();
+        generator.set_insertion_delay(1, 12.5).unwrap();
+        generator
+    }
+
+    #[test]
+    fn chained_channels_round_and_add_insertion_delay() {
+        let generator = generator();
+        assert_eq!(generator.setting(0), Some(100.0));
+        assert_eq!(generator.setting(1), Some(150.0));
+        assert_eq!(generator.output_time(1), Some(162.5));
+
+        let mut event_graph = DelayGraph::new();
+        generator.apply(&mut event_graph).unwrap();
+        assert_eq!(event_graph.get_delay(0, 1, 0, 2), Some(62.5));
+    }
+
+    #[test]
+    fn bad_references_are_rejected() {
+        let mut generator = generator();
+        assert!(generator.add_channel("C", 3, Reference::Channel(5), 0.0).is_err());
+        assert!(generator.add_channel("C", 2, Reference::T0, 0.0).is_err());
+    }
+
+    #[test]
+    fn settings_from_plan() {
+        let mut generator = generator();
+        let mut event_graph = DelayGraph::new();
+        generator.add_channels_to(&mut event_graph, 1000.0).unwrap();
+        // the master trigger reaches the generator 40 after T0 of the experiment
+        event_graph.add_time(1, 0, -40.0).unwrap();
+        event_graph.add_delay(1, 0, 0, 0, 0.0).unwrap();
+        let targets = [
+            Target::new(1, 0, 1, Interval::new(300.0, 300.0)),
+            Target::new(1, 0, 2, Interval::new(400.0, 400.0)),
+        ];
+        let plan = event_graph.plan(&targets).unwrap();
+        generator.set_from_plan(&plan).unwrap();
+        assert_eq!(generator.setting(0), Some(340.0));
+        assert_eq!(generator.output_time(1), Some(440.0));
+        assert_eq!(generator.channels[1].delay, 87.5);
+    }
+
+    #[test]
+    fn settings_must_be_finite() {
+        for resolution in [0.0, -0.5, f64::NAN, f64::INFINITY, 1e-320] {
+            assert!(matches!(DelayGenerator::new(0, 0, resolution), Err(Errors::InvalidBound)));
+        }
+        let mut generator = generator();
+        assert!(generator.add_channel("C", 3, Reference::T0, f64::NAN).is_err());
+        assert!(generator.set_delay(0, f64::INFINITY).is_err());
+        assert!(generator.set_insertion_delay(0, f64::NAN).is_err());
+        assert_eq!(generator, self::generator());
+
+        let mut plan = Plan {settings: Vec::new()};
+        for (event, delay) in [(1, 300.0), (2, f64::NAN)] {
+            let (from, to) = (TimebaseEventKey::new(0, 0), TimebaseEventKey::new(0, event));
+            plan.settings.push(ChannelSetting {from, to, delay});
+        }
+        assert!(matches!(generator.set_from_plan(&plan), Err(Errors::InvalidBound)));
+        assert_eq!(generator.setting(0), Some(100.0));
+    }
+
+    #[test]
+    fn settings_table() {
+        let table = generator().settings_table();
+        assert_eq!(table, "channel,reference,delay,insertion delay,output\nA,T0,100,0,100\nB,A,50,12.5,162.5\n");
+    }
+}
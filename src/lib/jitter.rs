@@ -0,0 +1,139 @@
+use crate::{DelayGraph, Errors, Event, TimebaseEventKey};
+
+/// RMS jitter of one link in the path of a derived quantity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JitterContribution {
+    pub from: TimebaseEventKey,
+    pub to: TimebaseEventKey,
+    pub rms: f64,
+}
+
+/// Jitter of a derived quantity, with the links it comes from largest first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimingBudget {
+    pub contributions: Vec<JitterContribution>,
+    /// Contributions summed in quadrature.
+    pub total: f64,
+}
+
+impl TimingBudget {
+    pub fn dominant(&self) -> Option<&JitterContribution> {
+        self.contributions.first()
+    }
+
+    /// Share of the total variance due to each contribution, in the same order.
+    pub fn variance_fractions(&self) -> Vec<f64> {
+        let variance = self.total * self.total;
+        self.contributions.iter()
+            .map(|contribution| if variance > 0.0 { contribution.rms * contribution.rms / variance } else { 0.0 })
+            .collect()
+    }
+}
+
+/// Jitter doesn't depend on direction, so links are stored with their ends in order.
+pub(crate) fn link(key_1: TimebaseEventKey, key_2: TimebaseEventKey) -> (TimebaseEventKey, TimebaseEventKey) {
+    if key_1 <= key_2 { (key_1, key_2) } else { (key_2, key_1) }
+}
+
+impl DelayGraph {
+
+    /// Set the RMS jitter of an entered delay, e.g. that of a delay generator channel.
+    pub fn set_jitter(&mut self, timebase_1: usize, event_1: usize, timebase_2: usize, event_2: usize, rms: f64) -> Result<(), Errors> {
+        let key_1 = TimebaseEventKey::new(timebase_1, event_1);
+        let key_2 = TimebaseEventKey::new(timebase_2, event_2);
+        self.set_link_jitter(key_1, key_2, rms)
+    }
+
+    /// Set the RMS jitter of an entered time, e.g. the trigger jitter of the scope it was read from.
+    pub fn set_time_jitter(&mut self, timebase: usize, event: usize, rms: f64) -> Result<(), Errors> {
+        let key = TimebaseEventKey::new(timebase, event);
+        self.set_link_jitter(key.t0_key(), key, rms)
+    }
+
+    fn set_link_jitter(&mut self, key_1: TimebaseEventKey, key_2: TimebaseEventKey, rms: f64) -> Result<(), Errors> {
+        if rms.is_nan() || rms < 0.0 {
+            return Err(Errors::InvalidBound)
+        }
+        if !self.graph.contains_edge(key_1, key_2) {
+            return Err(Errors::NotFound)
+        }
+        self.jitter.insert(link(key_1, key_2), rms);
+        Ok(())
+    }
+
+    pub fn lookup_jitter(&self, timebase_1: usize, event_1: usize, timebase_2: usize, event_2: usize) -> Option<&f64> {
+        let key_1 = TimebaseEventKey::new(timebase_1, event_1);
+        let key_2 = TimebaseEventKey::new(timebase_2, event_2);
+        self.jitter.get(&link(key_1, key_2))
+    }
+
+    /// Jitter budget of the delay between two events, following the same path as `calculate_delay`.
+    pub fn timing_budget(&self, timebase_1: usize, event_1: Event<usize>, timebase_2: usize, event_2: Event<usize>) -> Option<TimingBudget> {
+        let path = self.path(timebase_1, event_1, timebase_2, event_2)?;
+        let mut contributions: Vec<JitterContribution> = path.windows(2)
+            .filter_map(|pair| {
+                let rms = *self.jitter.get(&link(pair[0], pair[1]))?;
+                Some(JitterContribution {from: pair[0], to: pair[1], rms})
+            })
+            .filter(|contribution| contribution.rms > 0.0)
+            .collect();
+        contributions.sort_by(|a, b| b.rms.total_cmp(&a.rms));
+        let total = contributions.iter().map(|contribution| contribution.rms * contribution.rms).sum::<f64>().sqrt();
+        Some(TimingBudget {contributions, total})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    ///  0
+    ///  |------->  trigger box
+    ///   \ 100, jitter 0.03
+    ///    |--|--->  scope, trigger jitter 0.04
+    ///    0  50
+    fn jitter_adds_in_quadrature() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 0.0).unwrap();
+        event_graph.add_time(2, 1, 0.0).unwrap();
+        event_graph.add_time(2, 2, 50.0).unwrap();
+        event_graph.add_delay(1, 1, 2, 1, 100.0).unwrap();
+        event_graph.set_jitter(2, 1, 1, 1, 0.03).unwrap();
+        event_graph.set_time_jitter(2, 1, 0.04).unwrap();
+
+        let budget = event_graph.timing_budget(1, Event::Event(1), 2, Event::Event(2)).unwrap();
+        assert!((budget.total - 0.05).abs() < 1e-12);
+        let dominant = budget.dominant().unwrap();
+        assert_eq!((dominant.from, dominant.to), (TimebaseEventKey::new(2, 1), TimebaseEventKey::new_t0(2)));
+        let fractions = budget.variance_fractions();
+        assert!((fractions[0] - 0.64).abs() < 1e-12);
+        assert!((fractions[1] - 0.36).abs() < 1e-12);
+    }
+
+    #[test]
+    fn jitter_needs_an_entered_link() {
+        let mut event_graph = DelayGraph::new();
+        assert!(event_graph.set_jitter(1, 1, 2, 1, 0.1).is_err());
+        event_graph.add_delay(1, 1, 2, 1, 10.0).unwrap();
+        assert!(event_graph.set_jitter(1, 1, 2, 1, -0.1).is_err());
+        event_graph.set_jitter(1, 1, 2, 1, 0.1).unwrap();
+        assert_eq!(event_graph.lookup_jitter(2, 1, 1, 1), Some(&0.1));
+    }
+
+    #[test]
+    fn jitter_removed_with_its_link() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_delay(1, 1, 2, 1, 10.0).unwrap();
+        event_graph.set_jitter(1, 1, 2, 1, 0.1).unwrap();
+        event_graph.add_time(1, 1, 0.0).unwrap();
+        event_graph.set_time_jitter(1, 1, 0.2).unwrap();
+        event_graph.remove_delay(2, 1, 1, 1).unwrap();
+        event_graph.remove_time(1, 1).unwrap();
+
+        event_graph.add_delay(1, 1, 2, 1, 10.0).unwrap();
+        event_graph.add_time(1, 1, 0.0).unwrap();
+        assert_eq!(event_graph.lookup_jitter(1, 1, 2, 1), None);
+        assert_eq!(event_graph.timing_budget(1, Event::T0, 1, Event::Event(1)).unwrap().total, 0.0);
+    }
+}
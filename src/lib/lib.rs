@@ -1,11 +1,67 @@
 use petgraph::algo;
 use petgraph::graphmap::DiGraphMap;
+use petgraph::visit::Bfs;
+use std::collections::{BTreeMap, BTreeSet};
 use std::hash::Hash;
 
+pub mod bounds;
+pub mod causality;
+pub mod correlation;
+pub mod export;
+pub mod fiducial;
+pub mod generator;
+pub mod jitter;
+pub mod monte_carlo;
+pub mod planner;
+pub mod project;
+pub mod readers;
+pub mod recording;
+pub mod scpi;
+pub mod sensitivity;
+pub mod simulation;
+pub mod waveform;
+
+use bounds::Interval;
+use monte_carlo::Distribution;
+use recording::RecordWindow;
+
 #[derive(Debug)]
 pub enum Errors {
     AlreadyExists,
-    AlreadyConstrained(()),
+    /// Already derived from the other entries, which are summed along the path given.
+    AlreadyConstrained(Vec<Term>),
+    NotFound,
+    InvalidBound,
+    Infeasible(Vec<TimebaseEventKey>),
+    Unsupported,
+    LengthMismatch,
+    /// Data that must be on one timebase is on several.
+    TimebaseMismatch,
+    InvalidFile(String),
+}
+
+impl std::fmt::Display for Errors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Errors::AlreadyExists => write!(f, "already entered"),
+            Errors::AlreadyConstrained(terms) => {
+                let total: f64 = terms.iter().map(|term| term.value).sum();
+                let mut nodes: Vec<String> = terms.iter().map(|term| term.quantity.from.to_string()).collect();
+                nodes.extend(terms.last().map(|term| term.quantity.to.to_string()));
+                write!(f, "already derived as {} via {}", total, nodes.join(" -> "))
+            },
+            Errors::NotFound => write!(f, "not found"),
+            Errors::InvalidBound => write!(f, "invalid bound"),
+            Errors::Infeasible(cycle) => {
+                let nodes: Vec<String> = cycle.iter().map(|key| key.to_string()).collect();
+                write!(f, "constraints contradict each other around {}", nodes.join(" -> "))
+            },
+            Errors::Unsupported => write!(f, "not supported"),
+            Errors::LengthMismatch => write!(f, "lengths do not match"),
+            Errors::TimebaseMismatch => write!(f, "not on the same timebase"),
+            Errors::InvalidFile(message) => write!(f, "invalid file: {}", message),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -15,9 +71,9 @@ pub enum Event<E> {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
-struct TimebaseEventKey {
-    timebase: usize,
-    event: Event<usize>
+pub struct TimebaseEventKey {
+    pub timebase: usize,
+    pub event: Event<usize>
 }
 
 impl TimebaseEventKey {
@@ -35,9 +91,60 @@ impl TimebaseEventKey {
     }
 }
 
+impl std::fmt::Display for TimebaseEventKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.event {
+            Event::Event(event) => write!(f, "({}, {})", self.timebase, event),
+            Event::T0 => write!(f, "({}, T0)", self.timebase),
+        }
+    }
+}
+
+/// A time or delay that can be derived from the graph, from the first node to the second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Quantity {
+    pub from: TimebaseEventKey,
+    pub to: TimebaseEventKey,
+}
+
+impl Quantity {
+    pub fn time(timebase: usize, event: usize) -> Self {
+        Self {from: TimebaseEventKey::new_t0(timebase), to: TimebaseEventKey::new(timebase, event)}
+    }
+
+    pub fn delay(timebase_1: usize, event_1: usize, timebase_2: usize, event_2: usize) -> Self {
+        Self {from: TimebaseEventKey::new(timebase_1, event_1), to: TimebaseEventKey::new(timebase_2, event_2)}
+    }
+
+    /// Whether this is a time on a single timebase rather than a delay between events.
+    pub fn is_time(&self) -> bool {
+        self.from.event == Event::T0
+    }
+
+    pub fn evaluate(&self, graph: &DelayGraph) -> Option<f64> {
+        graph.calculate_delay(self.from.timebase, self.from.event, self.to.timebase, self.to.event)
+    }
+}
+
+/// One entered time or delay used to derive another, followed from `quantity.from` to `quantity.to`,
+/// so `value` is negative when an entry is followed backwards.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Term {
+    pub quantity: Quantity,
+    pub value: f64,
+}
+
 #[derive(Debug, Clone)]
 pub struct DelayGraph {
     graph: DiGraphMap<TimebaseEventKey, f64>,
+    bounds: BTreeMap<(TimebaseEventKey, TimebaseEventKey), Interval>,
+    channels: BTreeMap<(TimebaseEventKey, TimebaseEventKey), Interval>,
+    windows: BTreeMap<usize, RecordWindow>,
+    physical: BTreeSet<(TimebaseEventKey, TimebaseEventKey)>,
+    /// Each entered delay in the direction it was entered, which the edges can't tell
+    entered: BTreeSet<(TimebaseEventKey, TimebaseEventKey)>,
+    jitter: BTreeMap<(TimebaseEventKey, TimebaseEventKey), f64>,
+    distributions: BTreeMap<(TimebaseEventKey, TimebaseEventKey), Distribution>,
 }
 
 impl DelayGraph {
@@ -45,7 +152,14 @@ impl DelayGraph {
     /// Create an empty `EventGraph`
     pub fn new() -> Self {
         let graph = DiGraphMap::<TimebaseEventKey, f64>::new();
-        Self {graph}
+        let bounds = BTreeMap::new();
+        let channels = BTreeMap::new();
+        let windows = BTreeMap::new();
+        let physical = BTreeSet::new();
+        let entered = BTreeSet::new();
+        let jitter = BTreeMap::new();
+        let distributions = BTreeMap::new();
+        Self {graph, bounds, channels, windows, physical, entered, jitter, distributions}
     }
 
     /// Directly add a time to the graph, 
@@ -66,8 +180,8 @@ impl DelayGraph {
             return Err(Errors::AlreadyExists)
         };
         // try to calculate it
-        if let Some(_) = self.calculate_time(timebase.clone(), event.clone()) {
-            return Err(Errors::AlreadyConstrained(()))
+        if let Some(terms) = self.explain(&Quantity::time(timebase, event)) {
+            return Err(Errors::AlreadyConstrained(terms))
         };
         // if both are None then we need to add it, don't need to check existance as it would've
         // showed up with lookup_time
@@ -79,16 +193,16 @@ impl DelayGraph {
     pub fn update_time(&mut self, timebase: usize, event: usize, time: f64) -> Result<(), Errors> {
         if let Err(e) = self.add_time(timebase, event, time) {
             match e {
-                Errors::AlreadyConstrained(_) => Err(Errors::AlreadyConstrained(())),
                 Errors::AlreadyExists => {
                     let key = TimebaseEventKey::new(timebase, event);
                     let t0_key = TimebaseEventKey::new_t0(timebase);
                     
                     self.graph.add_edge(t0_key, key, time);
-                    self.graph.add_edge(t0_key, key, time);
+                    self.graph.add_edge(key, t0_key, -time);
 
                     Ok(())
                 },
+                e => Err(e),
             }
         } else {
             Ok(())
@@ -99,8 +213,11 @@ impl DelayGraph {
         let t0_key = TimebaseEventKey::new_t0(timebase);
         let key = TimebaseEventKey::new(timebase, event);
 
-        self.graph.remove_edge(t0_key, key).unwrap();
-        self.graph.remove_edge(key, t0_key).unwrap();
+        if self.graph.remove_edge(t0_key, key).is_none() {
+            return Err(Errors::NotFound)
+        }
+        self.graph.remove_edge(key, t0_key);
+        self.forget_link(t0_key, key);
 
         Ok(())
     }
@@ -109,9 +226,8 @@ impl DelayGraph {
         if let Some(_) = self.lookup_delay(timebase_1, event_1, timebase_2, event_2) {
             return Err(Errors::AlreadyExists)
         }
-        if let Some(_) = self.calculate_delay(timebase_1, Event::Event(event_1), timebase_2, Event::Event(event_2)) {
-            // panic!("apparently theres a delay of {}", delay);
-            return Err(Errors::AlreadyConstrained(()))
+        if let Some(terms) = self.explain(&Quantity::delay(timebase_1, event_1, timebase_2, event_2)) {
+            return Err(Errors::AlreadyConstrained(terms))
         }
         
         let key_1 = TimebaseEventKey::new(timebase_1, event_1);
@@ -120,13 +236,13 @@ impl DelayGraph {
         // then add the delays as edges
         self.graph.add_edge(key_1, key_2, delay);
         self.graph.add_edge(key_2, key_1, -delay);
+        self.entered.insert((key_1, key_2));
         Ok(())
     }
 
     pub fn update_delay(&mut self, timebase_1: usize, event_1: usize, timebase_2: usize, event_2: usize, delay: f64) -> Result<(), Errors> {
         if let Err(e) = self.add_delay(timebase_1, event_1, timebase_2, event_2, delay) {
             match e {
-                Errors::AlreadyConstrained(_) => Err(Errors::AlreadyConstrained(())),
                 Errors::AlreadyExists => {
                     // just load it up and change the weight
                     let key_1 = TimebaseEventKey::new(timebase_1, event_1);
@@ -138,6 +254,7 @@ impl DelayGraph {
                     
                     Ok(())
                 },
+                e => Err(e),
             }
         } else {
             Ok(())
@@ -148,19 +265,45 @@ impl DelayGraph {
         let key_1 = TimebaseEventKey::new(timebase_1, event_1);
         let key_2 = TimebaseEventKey::new(timebase_2, event_2);
         
-        self.graph.remove_edge(key_1, key_2).unwrap();
-        self.graph.remove_edge(key_2, key_1).unwrap();
+        if self.graph.remove_edge(key_1, key_2).is_none() {
+            return Err(Errors::NotFound)
+        }
+        self.graph.remove_edge(key_2, key_1);
+        self.entered.remove(&(key_1, key_2));
+        self.entered.remove(&(key_2, key_1));
+        self.forget_link(key_1, key_2);
 
         Ok(())
     }
 
+    /// Drop what was set on the link between `key_1` and `key_2` once its entry is removed,
+    /// so entering it again starts afresh.
+    fn forget_link(&mut self, key_1: TimebaseEventKey, key_2: TimebaseEventKey) {
+        self.jitter.remove(&jitter::link(key_1, key_2));
+        self.distributions.remove(&jitter::link(key_1, key_2));
+        self.physical.remove(&(key_1, key_2));
+        self.physical.remove(&(key_2, key_1));
+    }
+
+    /// Remove the entered time or delay `quantity` refers to, whichever way round it is followed.
+    pub fn remove_entry(&mut self, quantity: &Quantity) -> Result<(), Errors> {
+        let Quantity {from, to} = *quantity;
+        match (from.event, to.event) {
+            (Event::T0, Event::Event(event)) if from.timebase == to.timebase => self.remove_time(to.timebase, event),
+            (Event::Event(event), Event::T0) if from.timebase == to.timebase => self.remove_time(from.timebase, event),
+            (Event::Event(event_1), Event::Event(event_2)) => self.remove_delay(from.timebase, event_1, to.timebase, event_2),
+            _ => Err(Errors::NotFound),
+        }
+    }
+
     pub fn lookup_delay(&self, timebase_1: usize, event_1: usize, timebase_2: usize, event_2: usize) -> Option<&f64> {
         let key_1 = TimebaseEventKey::new(timebase_1, event_1);
         let key_2 = TimebaseEventKey::new(timebase_2, event_2);
         self.graph.edge_weight(key_1, key_2)
     }
 
-    pub fn calculate_delay(&self, timebase_1: usize, event_1: Event<usize>, timebase_2: usize, event_2: Event<usize>) -> Option<f64> {
+    /// The chain of nodes linking two events, if there is exactly one.
+    pub fn path(&self, timebase_1: usize, event_1: Event<usize>, timebase_2: usize, event_2: Event<usize>) -> Option<Vec<TimebaseEventKey>> {
         // generate keys to specify path
         let start_key = TimebaseEventKey {timebase: timebase_1, event: event_1};
         let finish_key = TimebaseEventKey {timebase: timebase_2, event: event_2};
@@ -168,19 +311,48 @@ impl DelayGraph {
         if self.graph.node_count() < 1 {
             return None
         }
-        let paths = algo::all_simple_paths(&self.graph, start_key, finish_key, 0, None).collect::<Vec<Vec<TimebaseEventKey>>>();
+        let mut paths = algo::all_simple_paths(&self.graph, start_key, finish_key, 0, None).collect::<Vec<Vec<TimebaseEventKey>>>();
         if paths.len() == 1 {
-            let path = &paths[0];
-            let mut sum = 0.0;
-            for i in 0..(path.len()-1) {
-                sum += *self.graph.edge_weight(path[i], path[i+1]).unwrap();
-            }
-            Some(sum)
+            paths.pop()
         } else {
             None
         }
     }
 
+    /// Every node that can be reached from `event` on `timebase` through entered times and delays, itself included,
+    /// however many paths there are.
+    pub fn connected(&self, timebase: usize, event: Event<usize>) -> BTreeSet<TimebaseEventKey> {
+        let start = TimebaseEventKey {timebase, event};
+        let mut connected = BTreeSet::from([start]);
+        if self.graph.contains_node(start) {
+            let mut bfs = Bfs::new(&self.graph, start);
+            while let Some(key) = bfs.next(&self.graph) {
+                connected.insert(key);
+            }
+        }
+        connected
+    }
+
+    /// Sum of the edge weights along `path`.
+    fn path_delay(&self, path: &[TimebaseEventKey]) -> f64 {
+        path.windows(2).map(|pair| *self.graph.edge_weight(pair[0], pair[1]).unwrap()).sum()
+    }
+
+    /// The entries summed to derive `quantity`, in order along the path, if it can be derived.
+    pub fn explain(&self, quantity: &Quantity) -> Option<Vec<Term>> {
+        let Quantity {from, to} = *quantity;
+        let path = self.path(from.timebase, from.event, to.timebase, to.event)?;
+        Some(path.windows(2).map(|pair| {
+            let value = *self.graph.edge_weight(pair[0], pair[1]).unwrap();
+            Term {quantity: Quantity {from: pair[0], to: pair[1]}, value}
+        }).collect())
+    }
+
+    pub fn calculate_delay(&self, timebase_1: usize, event_1: Event<usize>, timebase_2: usize, event_2: Event<usize>) -> Option<f64> {
+        let path = self.path(timebase_1, event_1, timebase_2, event_2)?;
+        Some(self.path_delay(&path))
+    }
+
     pub fn get_delay(&self, timebase_1: usize, event_1: usize, timebase_2: usize, event_2: usize) -> Option<f64> {
         if let Some(delay) = self.lookup_delay(timebase_1, event_1, timebase_2, event_2) {
             Some(*delay)
@@ -216,6 +388,64 @@ impl DelayGraph {
         }
     }
 
+    /// Every entered time as `(timebase, event, time)`.
+    pub fn times(&self) -> Vec<(usize, usize, f64)> {
+        let mut times: Vec<_> = self.graph.all_edges()
+            .filter_map(|(a, b, time)| match (a.event, b.event) {
+                (Event::T0, Event::Event(event)) => Some((b.timebase, event, *time)),
+                _ => None,
+            })
+            .collect();
+        times.sort_by_key(|a| (a.0, a.1));
+        times
+    }
+
+    /// Every entered delay once, as `(timebase_1, event_1, timebase_2, event_2, delay)`
+    /// in the direction it was entered.
+    pub fn delays(&self) -> Vec<(usize, usize, usize, usize, f64)> {
+        let mut delays: Vec<_> = self.graph.all_edges()
+            .filter(|&(a, b, _)| self.entered.contains(&(a, b)) || (a < b && !self.entered.contains(&(b, a))))
+            .filter_map(|(a, b, delay)| match (a.event, b.event) {
+                (Event::Event(event_1), Event::Event(event_2)) => Some((a.timebase, event_1, b.timebase, event_2, *delay)),
+                _ => None,
+            })
+            .collect();
+        delays.sort_by_key(|a| (a.0, a.1, a.2, a.3));
+        delays
+    }
+
+    /// Remove every time, delay and setting involving `timebase`.
+    pub fn remove_timebase(&mut self, timebase: usize) {
+        self.retain_keys(|key| key.timebase != timebase);
+        self.windows.remove(&timebase);
+    }
+
+    /// Remove every time, delay and setting involving `event`, on any timebase,
+    /// and the record windows it triggers.
+    pub fn remove_event(&mut self, event: usize) {
+        self.retain_keys(|key| key.event != Event::Event(event));
+        self.windows.retain(|_, window| window.trigger != Event::Event(event));
+    }
+
+    fn retain_keys(&mut self, keep: impl Fn(&TimebaseEventKey) -> bool) {
+        let removed: Vec<_> = self.graph.nodes().filter(|key| !keep(key)).collect();
+        for key in removed {
+            self.graph.remove_node(key);
+        }
+        // a T0 left without any times is no longer part of the graph
+        let bare: Vec<_> = self.graph.nodes().filter(|&key| self.graph.neighbors(key).next().is_none()).collect();
+        for key in bare {
+            self.graph.remove_node(key);
+        }
+        let keep_link = |(key_1, key_2): &(TimebaseEventKey, TimebaseEventKey)| keep(key_1) && keep(key_2);
+        self.bounds.retain(|link, _| keep_link(link));
+        self.channels.retain(|link, _| keep_link(link));
+        self.physical.retain(keep_link);
+        self.entered.retain(keep_link);
+        self.jitter.retain(|link, _| keep_link(link));
+        self.distributions.retain(|link, _| keep_link(link));
+    }
+
     pub fn neighbors(&self, timebase: usize, event: usize) -> usize {
         let key = TimebaseEventKey::new(timebase, event);
         self.graph.neighbors(key).count()
@@ -240,6 +470,51 @@ mod tests {
         // assert_eq!(event_graph.get_delay(1, 1, 1, 2).unwrap(), 10.)
     }
 
+    #[test]
+    ///     20   30
+    /// |---|----|--->
+    ///      <-->
+    fn update_time_moves_both_edges() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 0.).unwrap();
+        event_graph.add_time(1, 2, 30.).unwrap();
+        event_graph.update_time(1, 1, 20.).unwrap();
+        // the delay is followed back through T0, so uses the reverse edge of the updated time
+        assert_eq!(event_graph.get_delay(1, 1, 1, 2).unwrap(), 10.);
+        assert_eq!(event_graph.get_delay(1, 2, 1, 1).unwrap(), -10.);
+    }
+
+    #[test]
+    fn connected_through_cycles() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 0.).unwrap();
+        event_graph.add_time(1, 2, 10.).unwrap();
+        event_graph.add_delay(1, 1, 2, 1, 5.).unwrap();
+        event_graph.add_delay(1, 2, 2, 2, 5.).unwrap();
+        // close a loop directly, which the checks on entry would refuse
+        let (key_1, key_2) = (TimebaseEventKey::new(2, 1), TimebaseEventKey::new(2, 2));
+        event_graph.graph.add_edge(key_1, key_2, 10.);
+        event_graph.graph.add_edge(key_2, key_1, -10.);
+        // more than one path, so the delay is not derived, but the nodes are still linked
+        assert!(event_graph.path(2, Event::Event(2), 1, Event::Event(1)).is_none());
+        let connected = event_graph.connected(2, Event::Event(2));
+        assert!(connected.contains(&TimebaseEventKey::new(1, 1)));
+        assert!(!connected.contains(&TimebaseEventKey::new(3, 1)));
+        assert_eq!(event_graph.connected(3, Event::Event(1)), BTreeSet::from([TimebaseEventKey::new(3, 1)]));
+    }
+
+    #[test]
+    fn delays_keep_the_entered_direction() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_delay(2, 1, 1, 1, 5.).unwrap();
+        event_graph.add_delay(1, 2, 2, 2, 3.).unwrap();
+        event_graph.update_delay(2, 2, 1, 2, -4.).unwrap();
+        assert_eq!(event_graph.delays(), vec![(1, 2, 2, 2, 4.), (2, 1, 1, 1, 5.)]);
+        event_graph.remove_delay(1, 1, 2, 1).unwrap();
+        event_graph.add_delay(1, 1, 2, 1, -5.).unwrap();
+        assert_eq!(event_graph.delays(), vec![(1, 1, 2, 1, -5.), (1, 2, 2, 2, 4.)]);
+    }
+
     #[test]
     ///    500   1000
     /// |---|-----|--->
@@ -321,4 +596,56 @@ mod tests {
       
         assert_eq!(event_graph.get_delay(1, 1, 1, 2).unwrap(), 100.0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    ///  0   ?
+    ///  |---|--->
+    ///   \0  \0
+    ///    |---|--->
+    ///   100 200
+    fn explain_derived_time() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 0.0).unwrap();
+        event_graph.add_time(2, 1, 100.0).unwrap();
+        event_graph.add_time(2, 2, 200.0).unwrap();
+        event_graph.add_delay(1, 1, 2, 1, 0.0).unwrap();
+        event_graph.add_delay(1, 2, 2, 2, 0.0).unwrap();
+
+        let quantity = Quantity::time(1, 2);
+        let terms = event_graph.explain(&quantity).unwrap();
+        let values: Vec<f64> = terms.iter().map(|term| term.value).collect();
+        assert_eq!(values, vec![0.0, 0.0, -100.0, 200.0, 0.0]);
+        assert_eq!(terms.first().unwrap().quantity.from, TimebaseEventKey::new_t0(1));
+        assert_eq!(terms.last().unwrap().quantity.to, TimebaseEventKey::new(1, 2));
+        assert_eq!(values.iter().sum::<f64>(), quantity.evaluate(&event_graph).unwrap());
+
+        assert!(event_graph.explain(&Quantity::time(3, 1)).is_none());
+    }
+
+    #[test]
+    ///    500   1000
+    /// |---|-----|--->
+    ///      <--->
+    ///       600?
+    fn replace_conflicting_entry() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 500.0).unwrap();
+        event_graph.add_time(1, 2, 1000.0).unwrap();
+        let terms = match event_graph.add_delay(1, 1, 1, 2, 600.0) {
+            Err(error @ Errors::AlreadyConstrained(_)) => {
+                assert_eq!(error.to_string(), "already derived as 500 via (1, 1) -> (1, T0) -> (1, 2)");
+                let Errors::AlreadyConstrained(terms) = error else { unreachable!() };
+                terms
+            },
+            other => panic!("expected a constraint error, got {:?}", other),
+        };
+        // drop the first time instead, which the path follows back from the event to T0
+        event_graph.remove_entry(&terms[0].quantity).unwrap();
+        assert!(event_graph.lookup_time(1, 1).is_none());
+        event_graph.add_delay(1, 1, 1, 2, 600.0).unwrap();
+        assert_eq!(event_graph.get_time(1, 1).unwrap(), 400.0);
+
+        assert!(matches!(event_graph.remove_time(1, 1), Err(Errors::NotFound)));
+        assert!(matches!(event_graph.remove_delay(1, 2, 2, 2), Err(Errors::NotFound)));
+    }
+}
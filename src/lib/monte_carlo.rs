@@ -0,0 +1,279 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::jitter::link;
+use crate::{DelayGraph, Errors, Quantity, TimebaseEventKey};
+
+/// Draws made for a bounded link before giving up on landing inside its bound.
+const MAX_DRAWS: usize = 10_000;
+
+/// Spread of an entered time or delay about its entered value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distribution {
+    Normal { std_dev: f64 },
+    Uniform { half_width: f64 },
+    Triangular { half_width: f64 },
+}
+
+impl Distribution {
+    pub(crate) fn is_valid(&self) -> bool {
+        let width = match *self {
+            Distribution::Normal { std_dev } => std_dev,
+            Distribution::Uniform { half_width } | Distribution::Triangular { half_width } => half_width,
+        };
+        width >= 0.0
+    }
+
+    /// Draw an offset from the entered value.
+    fn sample(&self, rng: &mut StdRng) -> f64 {
+        match *self {
+            Distribution::Normal { std_dev } => {
+                // Box-Muller, keeping u away from zero so the log is finite
+                let u: f64 = 1.0 - rng.gen::<f64>();
+                let v: f64 = rng.gen();
+                std_dev * (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+            },
+            Distribution::Uniform { half_width } => half_width * (2.0 * rng.gen::<f64>() - 1.0),
+            Distribution::Triangular { half_width } => half_width * (rng.gen::<f64>() - rng.gen::<f64>()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    /// Bin edges, one more than there are bins.
+    pub edges: Vec<f64>,
+    pub counts: Vec<usize>,
+}
+
+/// Sampled values of one derived quantity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Samples {
+    pub quantity: Quantity,
+    pub values: Vec<f64>,
+}
+
+impl Samples {
+    /// Mean of the samples, `None` if there are none.
+    pub fn mean(&self) -> Option<f64> {
+        if self.values.is_empty() {
+            return None
+        }
+        Some(self.values.iter().sum::<f64>() / self.values.len() as f64)
+    }
+
+    /// Sample standard deviation, `None` with fewer than two samples.
+    pub fn std_dev(&self) -> Option<f64> {
+        if self.values.len() < 2 {
+            return None
+        }
+        let mean = self.mean()?;
+        let n = self.values.len() as f64;
+        Some((self.values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt())
+    }
+
+    /// Value below which `percent` of the samples fall, interpolating between samples.
+    /// `None` if there are no samples.
+    pub fn percentile(&self, percent: f64) -> Option<f64> {
+        if self.values.is_empty() {
+            return None
+        }
+        let mut sorted = self.values.clone();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let position = (percent / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+        let below = position.floor() as usize;
+        let above = position.ceil() as usize;
+        Some(sorted[below] + (position - below as f64) * (sorted[above] - sorted[below]))
+    }
+
+    /// Counts in `bins` equal bins spanning the sampled range.
+    /// `None` if there are no samples or no bins.
+    pub fn histogram(&self, bins: usize) -> Option<Histogram> {
+        if self.values.is_empty() || bins == 0 {
+            return None
+        }
+        let min = self.values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = self.values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let width = (max - min) / bins as f64;
+        let edges = (0..=bins).map(|i| min + i as f64 * width).collect();
+        let mut counts = vec![0; bins];
+        for value in &self.values {
+            let bin = if width > 0.0 { ((value - min) / width) as usize } else { 0 };
+            counts[bin.min(bins - 1)] += 1;
+        }
+        Some(Histogram {edges, counts})
+    }
+}
+
+impl DelayGraph {
+
+    /// Give an entered delay a spread for Monte Carlo sampling.
+    pub fn set_distribution(&mut self, timebase_1: usize, event_1: usize, timebase_2: usize, event_2: usize, distribution: Distribution) -> Result<(), Errors> {
+        let key_1 = TimebaseEventKey::new(timebase_1, event_1);
+        let key_2 = TimebaseEventKey::new(timebase_2, event_2);
+        self.set_link_distribution(key_1, key_2, distribution)
+    }
+
+    /// Give an entered time a spread for Monte Carlo sampling.
+    pub fn set_time_distribution(&mut self, timebase: usize, event: usize, distribution: Distribution) -> Result<(), Errors> {
+        let key = TimebaseEventKey::new(timebase, event);
+        self.set_link_distribution(key.t0_key(), key, distribution)
+    }
+
+    fn set_link_distribution(&mut self, key_1: TimebaseEventKey, key_2: TimebaseEventKey, distribution: Distribution) -> Result<(), Errors> {
+        if !distribution.is_valid() {
+            return Err(Errors::InvalidBound)
+        }
+        if !self.graph.contains_edge(key_1, key_2) {
+            return Err(Errors::NotFound)
+        }
+        self.distributions.insert(link(key_1, key_2), distribution);
+        Ok(())
+    }
+
+    /// Every uncertain link: those given a distribution, and those with only
+    /// a jitter, which is taken as normally distributed.
+    fn uncertain_links(&self) -> Vec<((TimebaseEventKey, TimebaseEventKey), Distribution)> {
+        let mut links: Vec<_> = self.distributions.iter().map(|(&link, &distribution)| (link, distribution)).collect();
+        for (&link, &rms) in &self.jitter {
+            if !self.distributions.contains_key(&link) && rms > 0.0 {
+                links.push((link, Distribution::Normal { std_dev: rms }));
+            }
+        }
+        links
+    }
+
+    /// Sample every uncertain link `n_samples` times, re-solving the graph for each sample.
+    /// Draws for a link with a bound are repeated until they fall inside it, so the samples
+    /// follow the distribution cut off at the entered bounds, and if none do the bound is
+    /// reported as `Errors::InvalidBound`. The same `seed` always gives the same samples.
+    pub fn monte_carlo(&self, quantities: &[Quantity], n_samples: usize, seed: u64) -> Result<Vec<Samples>, Errors> {
+        if quantities.iter().any(|quantity| quantity.evaluate(self).is_none()) {
+            return Err(Errors::NotFound)
+        }
+        let links = self.uncertain_links();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut samples: Vec<Samples> = quantities.iter()
+            .map(|&quantity| Samples {quantity, values: Vec::with_capacity(n_samples)})
+            .collect();
+
+        let mut sampled = self.clone();
+        for _ in 0..n_samples {
+            for &((key_1, key_2), distribution) in &links {
+                let nominal = *self.graph.edge_weight(key_1, key_2).unwrap();
+                let value = match self.bound_between(key_1, key_2) {
+                    Some(bound) => (0..MAX_DRAWS)
+                        .map(|_| nominal + distribution.sample(&mut rng))
+                        .find(|&value| bound.contains(value))
+                        .ok_or(Errors::InvalidBound)?,
+                    None => nominal + distribution.sample(&mut rng),
+                };
+                sampled.graph.add_edge(key_1, key_2, value);
+                sampled.graph.add_edge(key_2, key_1, -value);
+            }
+            for samples in samples.iter_mut() {
+                samples.values.push(samples.quantity.evaluate(&sampled).unwrap());
+            }
+        }
+        Ok(samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uncertain_graph() -> DelayGraph {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 0.0).unwrap();
+        event_graph.add_time(2, 1, 2500.0).unwrap();
+        event_graph.add_time(2, 2, 2700.0).unwrap();
+        event_graph.add_delay(1, 1, 2, 1, 65.5).unwrap();
+        event_graph.set_distribution(1, 1, 2, 1, Distribution::Normal { std_dev: 3.0 }).unwrap();
+        event_graph.set_time_distribution(2, 2, Distribution::Uniform { half_width: 4.0 }).unwrap();
+        event_graph
+    }
+
+    #[test]
+    fn sampled_statistics() {
+        let event_graph = uncertain_graph();
+        let quantity = Quantity::delay(1, 1, 2, 2);
+        let samples = &event_graph.monte_carlo(&[quantity], 20000, 1).unwrap()[0];
+        // nominally 65.5 - 2500 + 2700, variance 9 + 16 / 3
+        assert!((samples.mean().unwrap() - 265.5).abs() < 0.1);
+        assert!((samples.std_dev().unwrap() - (9.0f64 + 16.0 / 3.0).sqrt()).abs() < 0.1);
+        assert!(samples.percentile(0.0).unwrap() < samples.percentile(50.0).unwrap());
+        assert!((samples.percentile(50.0).unwrap() - 265.5).abs() < 0.2);
+
+        let histogram = samples.histogram(10).unwrap();
+        assert_eq!(histogram.edges.len(), 11);
+        assert_eq!(histogram.counts.iter().sum::<usize>(), 20000);
+    }
+
+    #[test]
+    fn same_seed_same_samples() {
+        let event_graph = uncertain_graph();
+        let quantities = [Quantity::time(2, 2), Quantity::delay(1, 1, 2, 2)];
+        let first = event_graph.monte_carlo(&quantities, 100, 42).unwrap();
+        let second = event_graph.monte_carlo(&quantities, 100, 42).unwrap();
+        let third = event_graph.monte_carlo(&quantities, 100, 43).unwrap();
+        assert_eq!(first, second);
+        assert_ne!(first, third);
+    }
+
+    #[test]
+    fn triangular_stays_within_half_width() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 10.0).unwrap();
+        event_graph.set_time_distribution(1, 1, Distribution::Triangular { half_width: 1.0 }).unwrap();
+        let samples = &event_graph.monte_carlo(&[Quantity::time(1, 1)], 1000, 7).unwrap()[0];
+        assert!(samples.values.iter().all(|value| (9.0..=11.0).contains(value)));
+    }
+
+    #[test]
+    fn distribution_removed_with_its_link() {
+        let mut event_graph = uncertain_graph();
+        event_graph.remove_delay(1, 1, 2, 1).unwrap();
+        event_graph.remove_time(2, 2).unwrap();
+        event_graph.add_delay(1, 1, 2, 1, 65.5).unwrap();
+        event_graph.add_time(2, 2, 2700.0).unwrap();
+        let samples = &event_graph.monte_carlo(&[Quantity::delay(1, 1, 2, 2)], 100, 1).unwrap()[0];
+        assert!(samples.values.iter().all(|&value| value == 265.5));
+    }
+
+    #[test]
+    fn no_samples() {
+        let event_graph = uncertain_graph();
+        let samples = &event_graph.monte_carlo(&[Quantity::time(2, 2)], 0, 0).unwrap()[0];
+        assert_eq!(samples.mean(), None);
+        assert_eq!(samples.std_dev(), None);
+        assert_eq!(samples.percentile(50.0), None);
+        assert_eq!(samples.histogram(10), None);
+        let samples = &event_graph.monte_carlo(&[Quantity::time(2, 2)], 10, 0).unwrap()[0];
+        assert_eq!(samples.histogram(0), None);
+    }
+
+    #[test]
+    fn samples_stay_within_bounds() {
+        let mut event_graph = uncertain_graph();
+        // entered the other way round to the distribution
+        event_graph.add_bound(2, 1, 1, 1, -66.0, -64.0).unwrap();
+        let samples = &event_graph.monte_carlo(&[Quantity::delay(1, 1, 2, 1)], 4000, 3).unwrap()[0];
+        assert!(samples.values.iter().all(|value| (64.0..=66.0).contains(value)));
+        // normal about 65.5 with deviation 3 cut to the bound, so nearly flat with its mean just above 65
+        assert!((samples.mean().unwrap() - 65.02).abs() < 0.05);
+        let top = samples.values.iter().filter(|&&value| value > 65.9).count() as f64 / 4000.0;
+        assert!(top < 0.07);
+
+        // nothing of a narrow distribution reaches a bound far from it
+        let mut event_graph = uncertain_graph();
+        event_graph.add_bound(1, 1, 2, 1, 0.0, 1.0).unwrap();
+        assert!(matches!(event_graph.monte_carlo(&[Quantity::delay(1, 1, 2, 1)], 1, 3), Err(Errors::InvalidBound)));
+    }
+
+    #[test]
+    fn underivable_quantity() {
+        let event_graph = uncertain_graph();
+        assert!(event_graph.monte_carlo(&[Quantity::time(3, 1)], 10, 0).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+use crate::bounds::{bellman_ford, Edge, Interval};
+use crate::{DelayGraph, Errors, Event, Quantity, TimebaseEventKey};
+
+/// A requirement that an event happens within `window` on the `reference` timebase,
+/// e.g. that a detector triggers between t1 and t2 on the experiment timebase.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Target {
+    pub reference: usize,
+    pub timebase: usize,
+    pub event: usize,
+    pub window: Interval,
+}
+
+impl Target {
+    pub fn new(reference: usize, timebase: usize, event: usize, window: Interval) -> Self {
+        Self {reference, timebase, event, window}
+    }
+
+    /// Require the event to happen at exactly `time` on the reference timebase.
+    pub fn at(reference: usize, timebase: usize, event: usize, time: f64) -> Self {
+        Self::new(reference, timebase, event, Interval::new(time, time))
+    }
+}
+
+/// Chosen value of one adjustable delay.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelSetting {
+    pub from: TimebaseEventKey,
+    pub to: TimebaseEventKey,
+    pub delay: f64,
+}
+
+/// Settings for every adjustable delay that together meet all targets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plan {
+    pub settings: Vec<ChannelSetting>,
+}
+
+impl Plan {
+    pub fn get(&self, timebase_1: usize, event_1: usize, timebase_2: usize, event_2: usize) -> Option<f64> {
+        let from = TimebaseEventKey::new(timebase_1, event_1);
+        let to = TimebaseEventKey::new(timebase_2, event_2);
+        self.settings.iter()
+            .find(|setting| setting.from == from && setting.to == to)
+            .map(|setting| setting.delay)
+    }
+}
+
+impl DelayGraph {
+
+    /// Mark the delay from `event_1` on `timebase_1` to `event_2` on `timebase_2`
+    /// as adjustable, e.g. a delay generator channel, settable between `min` and `max`.
+    pub fn add_channel(&mut self, timebase_1: usize, event_1: usize, timebase_2: usize, event_2: usize, min: f64, max: f64) -> Result<(), Errors> {
+        let key_1 = TimebaseEventKey::new(timebase_1, event_1);
+        let key_2 = TimebaseEventKey::new(timebase_2, event_2);
+        if min.is_nan() || max.is_nan() || min > max {
+            return Err(Errors::InvalidBound)
+        }
+        if self.channels.contains_key(&(key_1, key_2)) || self.channels.contains_key(&(key_2, key_1)) {
+            return Err(Errors::AlreadyExists)
+        }
+        if self.lookup_delay(timebase_1, event_1, timebase_2, event_2).is_some() {
+            return Err(Errors::AlreadyExists)
+        }
+        if let Some(terms) = self.explain(&Quantity::delay(timebase_1, event_1, timebase_2, event_2)) {
+            return Err(Errors::AlreadyConstrained(terms))
+        }
+        self.channels.insert((key_1, key_2), Interval::new(min, max));
+        Ok(())
+    }
+
+    pub fn remove_channel(&mut self, timebase_1: usize, event_1: usize, timebase_2: usize, event_2: usize) -> Result<(), Errors> {
+        let key_1 = TimebaseEventKey::new(timebase_1, event_1);
+        let key_2 = TimebaseEventKey::new(timebase_2, event_2);
+        if self.channels.remove(&(key_1, key_2)).is_some() || self.channels.remove(&(key_2, key_1)).is_some() {
+            Ok(())
+        } else {
+            Err(Errors::NotFound)
+        }
+    }
+
+    /// Adjustable delays with their settable range, in the direction they were added.
+    pub fn channels(&self) -> impl Iterator<Item = (TimebaseEventKey, TimebaseEventKey, Interval)> + '_ {
+        self.channels.iter().map(|(&(from, to), &range)| (from, to, range))
+    }
+
+    /// Solve for channel settings that meet every target.
+    ///
+    /// Channels are fixed one at a time, each at the middle of the range still
+    /// open to it, so the settings sit as far from the edges as the targets allow.
+    /// If the targets can't be met the offending loop is returned in `Errors::Infeasible`.
+    pub fn plan(&self, targets: &[Target]) -> Result<Plan, Errors> {
+        let mut edges = self.constraint_edges();
+        for target in targets {
+            let reference = TimebaseEventKey::new_t0(target.reference);
+            let key = TimebaseEventKey::new(target.timebase, target.event);
+            if target.window.min.is_nan() || target.window.max.is_nan() || target.window.min > target.window.max {
+                return Err(Errors::InvalidBound)
+            }
+            if target.window.max.is_finite() {
+                edges.push((reference, key, target.window.max));
+            }
+            if target.window.min.is_finite() {
+                edges.push((key, reference, -target.window.min));
+            }
+        }
+        let nodes = Self::constraint_nodes(&edges, &[]);
+        bellman_ford(&nodes, &edges, None).map_err(Errors::Infeasible)?;
+
+        let mut settings = Vec::new();
+        for &(from, to) in self.channels.keys() {
+            let range = open_range(&nodes, &edges, from, to)?;
+            // a channel nothing depends on can sit anywhere in its own range
+            let delay = range.midpoint().unwrap_or(0.0);
+            edges.push((from, to, delay));
+            edges.push((to, from, -delay));
+            settings.push(ChannelSetting {from, to, delay});
+        }
+        Ok(Plan {settings})
+    }
+
+    /// Write the planned settings into the graph as ordinary delays,
+    /// all of them or, if any can't be written, none.
+    pub fn apply_plan(&mut self, plan: &Plan) -> Result<(), Errors> {
+        let mut applied = self.clone();
+        for setting in &plan.settings {
+            let (Event::Event(event_1), Event::Event(event_2)) = (setting.from.event, setting.to.event) else {
+                return Err(Errors::NotFound)
+            };
+            let key_1 = setting.from;
+            let key_2 = setting.to;
+            if !applied.channels.contains_key(&(key_1, key_2)) {
+                return Err(Errors::NotFound)
+            }
+            applied.update_delay(key_1.timebase, event_1, key_2.timebase, event_2, setting.delay)?;
+        }
+        *self = applied;
+        Ok(())
+    }
+}
+
+/// Range of `to - from` still allowed by `edges`.
+fn open_range(nodes: &[TimebaseEventKey], edges: &[Edge], from: TimebaseEventKey, to: TimebaseEventKey) -> Result<Interval, Errors> {
+    let upper = bellman_ford(nodes, edges, Some(from)).map_err(Errors::Infeasible)?;
+    let lower = bellman_ford(nodes, edges, Some(to)).map_err(Errors::Infeasible)?;
+    let max = upper.get(&to).copied().unwrap_or(f64::INFINITY);
+    let min = lower.get(&from).map(|d| -d).unwrap_or(f64::NEG_INFINITY);
+    Ok(Interval::new(min, max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// trigger box 0, scope 1, experiment 2; event 0 is the trigger, event 1 is current start
+    fn trigger_chain() -> DelayGraph {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(0, 0, 0.0).unwrap();
+        event_graph.add_time(2, 1, 0.0).unwrap();
+        event_graph.add_time(1, 0, 0.0).unwrap();
+        // current starts 500 after the master trigger
+        event_graph.add_delay(0, 0, 2, 1, 500.0).unwrap();
+        event_graph
+    }
+
+    #[test]
+    fn plan_scope_trigger() {
+        let mut event_graph = trigger_chain();
+        event_graph.add_channel(0, 0, 1, 0, 0.0, 1000.0).unwrap();
+        // the scope should trigger between 100 and 80 before current start
+        let target = Target::new(2, 1, 0, Interval::new(-100.0, -80.0));
+        let plan = event_graph.plan(&[target]).unwrap();
+        assert_eq!(plan.get(0, 0, 1, 0), Some(410.0));
+
+        event_graph.apply_plan(&plan).unwrap();
+        assert_eq!(event_graph.get_delay(1, 0, 2, 1), Some(90.0));
+    }
+
+    #[test]
+    fn chained_channels_are_centred() {
+        let mut event_graph = trigger_chain();
+        event_graph.add_time(3, 0, 0.0).unwrap();
+        event_graph.add_channel(0, 0, 1, 0, 0.0, 1000.0).unwrap();
+        event_graph.add_channel(1, 0, 3, 0, 0.0, 1000.0).unwrap();
+        let plan = event_graph.plan(&[Target::at(2, 3, 0, 100.0)]).unwrap();
+        // the second detector is 600 after the master trigger, split evenly
+        assert_eq!(plan.get(0, 0, 1, 0), Some(300.0));
+        assert_eq!(plan.get(1, 0, 3, 0), Some(300.0));
+    }
+
+    #[test]
+    fn unreachable_target_is_infeasible() {
+        let mut event_graph = trigger_chain();
+        event_graph.add_channel(0, 0, 1, 0, 0.0, 200.0).unwrap();
+        let target = Target::at(2, 1, 0, 0.0);
+        assert!(matches!(event_graph.plan(&[target]), Err(Errors::Infeasible(_))));
+    }
+
+    #[test]
+    fn invalid_window_is_rejected() {
+        let mut event_graph = trigger_chain();
+        event_graph.add_channel(0, 0, 1, 0, 0.0, 1000.0).unwrap();
+        for window in [Interval::new(f64::NAN, 0.0), Interval::new(0.0, f64::NAN), Interval::new(10.0, -10.0)] {
+            assert!(matches!(event_graph.plan(&[Target::new(2, 1, 0, window)]), Err(Errors::InvalidBound)));
+        }
+    }
+
+    #[test]
+    fn plan_applied_whole_or_not_at_all() {
+        let mut event_graph = trigger_chain();
+        event_graph.add_channel(0, 0, 1, 0, 0.0, 1000.0).unwrap();
+        let mut plan = event_graph.plan(&[Target::at(2, 1, 0, -90.0)]).unwrap();
+        plan.settings.push(ChannelSetting {from: TimebaseEventKey::new(1, 0), to: TimebaseEventKey::new(3, 0), delay: 5.0});
+        assert!(matches!(event_graph.apply_plan(&plan), Err(Errors::NotFound)));
+        assert_eq!(event_graph.get_delay(0, 0, 1, 0), None);
+    }
+
+    #[test]
+    fn fixed_delay_is_not_adjustable() {
+        let mut event_graph = trigger_chain();
+        assert!(event_graph.add_channel(0, 0, 2, 1, 0.0, 1000.0).is_err());
+    }
+}
@@ -0,0 +1,528 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use miniz_oxide::inflate::TINFLStatus;
+use serde::{Deserialize, Serialize};
+
+use crate::{DelayGraph, Errors, Event};
+
+/// A timebase or event name, with the id it is entered into the graph under.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub id: usize,
+    pub name: String,
+}
+
+/// Names in display order. Ids stay with their names when they are moved or renamed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Labels {
+    labels: Vec<Label>,
+}
+
+impl Labels {
+    pub fn iter(&self) -> impl Iterator<Item = &Label> {
+        self.labels.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn id(&self, name: &str) -> Option<usize> {
+        self.labels.iter().find(|label| label.name == name).map(|label| label.id)
+    }
+
+    pub fn name(&self, id: usize) -> Option<&str> {
+        self.labels.iter().find(|label| label.id == id).map(|label| label.name.as_str())
+    }
+
+    pub fn position(&self, id: usize) -> Option<usize> {
+        self.labels.iter().position(|label| label.id == id)
+    }
+
+    fn push(&mut self, id: usize, name: &str) -> Result<(), Errors> {
+        if self.id(name).is_some() {
+            return Err(Errors::AlreadyExists)
+        }
+        self.labels.push(Label {id, name: name.to_owned()});
+        Ok(())
+    }
+
+    fn remove(&mut self, id: usize) -> Result<(), Errors> {
+        let position = self.position(id).ok_or(Errors::NotFound)?;
+        self.labels.remove(position);
+        Ok(())
+    }
+
+    pub fn rename(&mut self, id: usize, name: &str) -> Result<(), Errors> {
+        if self.id(name).is_some_and(|other| other != id) {
+            return Err(Errors::AlreadyExists)
+        }
+        let position = self.position(id).ok_or(Errors::NotFound)?;
+        self.labels[position].name = name.to_owned();
+        Ok(())
+    }
+
+    /// Move the name with `id` to `index` in the display order.
+    pub fn move_to(&mut self, id: usize, index: usize) -> Result<(), Errors> {
+        let position = self.position(id).ok_or(Errors::NotFound)?;
+        let label = self.labels.remove(position);
+        self.labels.insert(index.min(self.labels.len()), label);
+        Ok(())
+    }
+}
+
+/// A row of an imported file that could not be entered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportError {
+    pub line: u64,
+    pub message: String,
+}
+
+/// Version of [`ProjectData`] written by this library.
+pub const PROJECT_VERSION: u32 = 1;
+
+/// Format of the links made by [`Project::to_link`], written before the first `.` so old links still open.
+pub const LINK_VERSION: u32 = 1;
+
+/// Largest project, as JSON, a link may expand to, so a crafted link can't exhaust memory.
+const MAX_LINK_SIZE: usize = 16 << 20;
+
+/// Everything entered in a project, keyed by name, as it is saved and shared.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectData {
+    pub version: u32,
+    pub timebases: Vec<String>,
+    pub events: Vec<String>,
+    /// `(timebase, event, time)`
+    pub times: Vec<(String, String, f64)>,
+    /// `(timebase_1, event_1, timebase_2, event_2, delay)`
+    pub delays: Vec<(String, String, String, String, f64)>,
+}
+
+/// A delay graph with names for its timebases and events.
+#[derive(Debug, Clone)]
+pub struct Project {
+    pub timebases: Labels,
+    pub events: Labels,
+    pub graph: DelayGraph,
+    next_id: usize,
+}
+
+impl Default for Project {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Project {
+    pub fn new() -> Self {
+        Self {timebases: Labels::default(), events: Labels::default(), graph: DelayGraph::new(), next_id: 0}
+    }
+
+    /// Project with `n_timebases` and `n_events` numbered placeholder names.
+    pub fn with_size(n_timebases: usize, n_events: usize) -> Self {
+        let mut project = Self::new();
+        for i in 0..n_timebases {
+            project.add_timebase(&format!("timebase {}", i)).unwrap();
+        }
+        for i in 0..n_events {
+            project.add_event(&format!("event {}", i)).unwrap();
+        }
+        project
+    }
+
+    pub fn add_timebase(&mut self, name: &str) -> Result<usize, Errors> {
+        self.timebases.push(self.next_id, name)?;
+        self.next_id += 1;
+        Ok(self.next_id - 1)
+    }
+
+    pub fn add_event(&mut self, name: &str) -> Result<usize, Errors> {
+        self.events.push(self.next_id, name)?;
+        self.next_id += 1;
+        Ok(self.next_id - 1)
+    }
+
+    /// Id of the timebase called `name`, adding it if there is none.
+    pub fn timebase_or_add(&mut self, name: &str) -> usize {
+        self.timebases.id(name).unwrap_or_else(|| self.add_timebase(name).unwrap())
+    }
+
+    /// Id of the event called `name`, adding it if there is none.
+    pub fn event_or_add(&mut self, name: &str) -> usize {
+        self.events.id(name).unwrap_or_else(|| self.add_event(name).unwrap())
+    }
+
+    /// Import a times file, with columns `timebase, event, time`, or a delays file, with columns
+    /// `timebase_1, event_1, timebase_2, event_2, delay`, told apart by the number of columns.
+    /// New names are added as they are found, and rows that can't be entered are skipped and reported.
+    pub fn import_csv(&mut self, data: &str) -> Result<Vec<ImportError>, Errors> {
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes());
+        let columns = reader.headers().map_err(|error| Errors::InvalidFile(error.to_string()))?.len();
+        if columns != 3 && columns != 5 {
+            return Err(Errors::InvalidFile(format!("expected 3 columns of times or 5 of delays, found {}", columns)))
+        }
+
+        // the reader counts blank lines into the record after them, so count from where the record starts
+        let bytes = data.as_bytes();
+        let line = |position: Option<&csv::Position>| position.map_or(0, |position| {
+            let mut start = position.byte() as usize;
+            start += bytes[start..].iter().take_while(|&&b| b == b'\n' || b == b'\r').count();
+            bytes[..start].iter().filter(|&&b| b == b'\n').count() as u64 + 1
+        });
+        let mut errors = Vec::new();
+        for record in reader.records() {
+            let (line, result) = match record {
+                Ok(record) => (line(record.position()), self.import_row(&record, columns)),
+                Err(error) => (line(error.position()), Err(error.to_string())),
+            };
+            if let Err(message) = result {
+                errors.push(ImportError {line, message});
+            }
+        }
+        Ok(errors)
+    }
+
+    /// Import several files as [`Project::import_csv`] does, times files before delays files so the delays
+    /// can be checked against them. Results are in the order of `files`.
+    pub fn import_csvs(&mut self, files: &[&str]) -> Vec<Result<Vec<ImportError>, Errors>> {
+        let is_times = |data: &str| {
+            let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(data.as_bytes());
+            matches!(reader.headers().map(|headers| headers.len()), Ok(3))
+        };
+        let mut order: Vec<usize> = (0..files.len()).collect();
+        order.sort_by_key(|&i| !is_times(files[i]));
+        let mut results: Vec<_> = files.iter().map(|_| Ok(Vec::new())).collect();
+        for i in order {
+            results[i] = self.import_csv(files[i]);
+        }
+        results
+    }
+
+    fn import_row(&mut self, record: &csv::StringRecord, columns: usize) -> Result<(), String> {
+        if record.iter().all(|cell| cell.is_empty()) {
+            return Ok(())
+        }
+        if record.len() != columns {
+            return Err(format!("expected {} columns, found {}", columns, record.len()))
+        }
+        if let Some(empty) = record.iter().position(|cell| cell.is_empty()) {
+            return Err(format!("column {} is empty", empty + 1))
+        }
+        let value = &record[columns - 1];
+        let value: f64 = value.parse().map_err(|_| format!("\"{}\" is not a number", value))?;
+        let result = if columns == 3 {
+            let (timebase, event) = (self.timebase_or_add(&record[0]), self.event_or_add(&record[1]));
+            self.graph.add_time(timebase, event, value)
+        } else {
+            let (timebase_1, event_1) = (self.timebase_or_add(&record[0]), self.event_or_add(&record[1]));
+            let (timebase_2, event_2) = (self.timebase_or_add(&record[2]), self.event_or_add(&record[3]));
+            self.graph.add_delay(timebase_1, event_1, timebase_2, event_2, value)
+        };
+        result.map_err(|error| error.to_string())
+    }
+
+    pub fn to_data(&self) -> ProjectData {
+        let timebase = |id| self.timebases.name(id).unwrap().to_owned();
+        let event = |id| self.events.name(id).unwrap().to_owned();
+        ProjectData {
+            version: PROJECT_VERSION,
+            timebases: self.timebases.iter().map(|label| label.name.clone()).collect(),
+            events: self.events.iter().map(|label| label.name.clone()).collect(),
+            times: self.graph.times().into_iter()
+                .map(|(tb, e, time)| (timebase(tb), event(e), time))
+                .collect(),
+            delays: self.graph.delays().into_iter()
+                .map(|(tb_1, e_1, tb_2, e_2, delay)| (timebase(tb_1), event(e_1), timebase(tb_2), event(e_2), delay))
+                .collect(),
+        }
+    }
+
+    pub fn from_data(data: &ProjectData) -> Result<Self, Errors> {
+        if data.version > PROJECT_VERSION {
+            return Err(Errors::Unsupported)
+        }
+        let mut project = Self::new();
+        for name in &data.timebases {
+            project.add_timebase(name)?;
+        }
+        for name in &data.events {
+            project.add_event(name)?;
+        }
+        for (timebase, event, time) in &data.times {
+            let (timebase, event) = (project.timebase_or_add(timebase), project.event_or_add(event));
+            project.graph.add_time(timebase, event, *time)?;
+        }
+        for (timebase_1, event_1, timebase_2, event_2, delay) in &data.delays {
+            let (timebase_1, event_1) = (project.timebase_or_add(timebase_1), project.event_or_add(event_1));
+            let (timebase_2, event_2) = (project.timebase_or_add(timebase_2), project.event_or_add(event_2));
+            project.graph.add_delay(timebase_1, event_1, timebase_2, event_2, *delay)?;
+        }
+        Ok(project)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.to_data()).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Errors> {
+        let data: ProjectData = serde_json::from_str(json).map_err(|error| Errors::InvalidFile(error.to_string()))?;
+        Self::from_data(&data)
+    }
+
+    /// The whole project as url safe text, to put in a link fragment.
+    pub fn to_link(&self) -> String {
+        let json = serde_json::to_vec(&self.to_data()).unwrap();
+        let compressed = miniz_oxide::deflate::compress_to_vec(&json, 9);
+        format!("{}.{}", LINK_VERSION, URL_SAFE_NO_PAD.encode(compressed))
+    }
+
+    pub fn from_link(link: &str) -> Result<Self, Errors> {
+        let invalid = |message: &str| Errors::InvalidFile(message.to_owned());
+        let (version, payload) = link.trim_start_matches('#').split_once('.').ok_or_else(|| invalid("no link version"))?;
+        match version.parse::<u32>().map_err(|_| invalid("no link version"))? {
+            1 => {
+                let compressed = URL_SAFE_NO_PAD.decode(payload).map_err(|error| invalid(&error.to_string()))?;
+                let json = miniz_oxide::inflate::decompress_to_vec_with_limit(&compressed, MAX_LINK_SIZE).map_err(|error| match error.status {
+                    TINFLStatus::HasMoreOutput => invalid("link expands to too much data"),
+                    _ => invalid("corrupt link"),
+                })?;
+                let data: ProjectData = serde_json::from_slice(&json).map_err(|error| invalid(&error.to_string()))?;
+                Self::from_data(&data)
+            },
+            _ => Err(Errors::Unsupported),
+        }
+    }
+
+    /// Entered times in the format [`Project::import_csv`] reads.
+    pub fn times_csv(&self) -> String {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(["timebase", "event", "time"]).unwrap();
+        for (timebase, event, time) in self.to_data().times {
+            writer.write_record([timebase, event, time.to_string()]).unwrap();
+        }
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+
+    /// Entered delays in the format [`Project::import_csv`] reads.
+    pub fn delays_csv(&self) -> String {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(["timebase_1", "event_1", "timebase_2", "event_2", "delay"]).unwrap();
+        for (timebase_1, event_1, timebase_2, event_2, delay) in self.to_data().delays {
+            writer.write_record([timebase_1, event_1, timebase_2, event_2, delay.to_string()]).unwrap();
+        }
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+
+    /// Entered or derived time of every event on every timebase, one row per timebase,
+    /// left empty where it can't be derived.
+    pub fn time_matrix_csv(&self) -> String {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(std::iter::once("timebase").chain(self.events.iter().map(|label| label.name.as_str()))).unwrap();
+        for timebase in self.timebases.iter() {
+            let times = self.events.iter().map(|event| {
+                self.graph.get_time(timebase.id, event.id).map_or(String::new(), |time| time.to_string())
+            });
+            writer.write_record(std::iter::once(timebase.name.clone()).chain(times)).unwrap();
+        }
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+
+    /// Python defining `times`, the entered or derived times on each timebase, and `offsets`,
+    /// the origin of each timebase relative to the first, with a function converting between them.
+    pub fn python_snippet(&self) -> String {
+        let quote = |name: &str| serde_json::to_string(name).unwrap();
+        let number = |value: Option<f64>| value.map_or("None".to_owned(), |value| format!("{:?}", value));
+        let mut lines = vec!["# generated by delays".to_owned(), "times = {".to_owned()];
+        for timebase in self.timebases.iter() {
+            let times: Vec<String> = self.events.iter()
+                .filter_map(|event| Some(format!("{}: {}", quote(&event.name), number(Some(self.graph.get_time(timebase.id, event.id)?)))))
+                .collect();
+            lines.push(format!("    {}: {{{}}},", quote(&timebase.name), times.join(", ")));
+        }
+        lines.push("}".to_owned());
+
+        if let Some(reference) = self.timebases.iter().next() {
+            lines.push(String::new());
+            lines.push(format!("# origin of each timebase after the origin of {}, None where unknown", quote(&reference.name)));
+            lines.push("offsets = {".to_owned());
+            for timebase in self.timebases.iter() {
+                let offset = if timebase.id == reference.id {
+                    Some(0.0)
+                } else {
+                    self.graph.calculate_delay(reference.id, Event::T0, timebase.id, Event::T0)
+                };
+                lines.push(format!("    {}: {},", quote(&timebase.name), number(offset)));
+            }
+            lines.push("}".to_owned());
+            lines.push(String::new());
+            lines.push("def convert(time, source, target):".to_owned());
+            lines.push("    \"\"\"Time on `target` of `time` on `source`, accounting only for the offset between their origins.\"\"\"".to_owned());
+            lines.push("    return time + offsets[source] - offsets[target]".to_owned());
+        }
+        lines.join("\n") + "\n"
+    }
+
+    /// Remove a timebase along with every time and delay entered on it.
+    pub fn remove_timebase(&mut self, id: usize) -> Result<(), Errors> {
+        self.timebases.remove(id)?;
+        self.graph.remove_timebase(id);
+        Ok(())
+    }
+
+    /// Remove an event along with every time and delay entered for it.
+    pub fn remove_event(&mut self, id: usize) -> Result<(), Errors> {
+        self.events.remove(id)?;
+        self.graph.remove_event(id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_follow_names() {
+        let mut project = Project::with_size(2, 2);
+        let scope = project.timebases.id("timebase 1").unwrap();
+        let trig = project.events.id("event 0").unwrap();
+        project.graph.add_time(scope, trig, -895.0).unwrap();
+
+        let extra = project.add_timebase("head scope").unwrap();
+        project.timebases.move_to(extra, 0).unwrap();
+        project.timebases.rename(scope, "pdv scope").unwrap();
+        let names: Vec<&str> = project.timebases.iter().map(|label| label.name.as_str()).collect();
+        assert_eq!(names, vec!["head scope", "timebase 0", "pdv scope"]);
+        assert_eq!(project.graph.get_time(project.timebases.id("pdv scope").unwrap(), trig), Some(-895.0));
+
+        assert!(project.add_event("event 1").is_err());
+        assert!(project.timebases.rename(extra, "pdv scope").is_err());
+    }
+
+    #[test]
+    fn import_example_files() {
+        let mut project = Project::new();
+        assert!(project.import_csv(include_str!("cepage_events.csv")).unwrap().is_empty());
+        assert!(project.import_csv(include_str!("cepage_delays.csv")).unwrap().is_empty());
+        assert_eq!(project.timebases.len(), 5);
+        let head_scope = project.timebases.id("head scope").unwrap();
+        let experiment = project.timebases.id("experiment").unwrap();
+        let xray_peak = project.events.id("xray peak").unwrap();
+        assert_eq!(project.graph.get_time(head_scope, project.events.id("ext out").unwrap()), Some(35.0));
+        // 330 after the current start on the head scope, which is 2500 - 65.5 after it on the experiment
+        assert_eq!(project.graph.get_delay(experiment, xray_peak, head_scope, xray_peak), Some(330.0));
+    }
+
+    #[test]
+    fn times_imported_before_delays() {
+        let mut project = Project::new();
+        let results = project.import_csvs(&[include_str!("cepage_delays.csv"), "a, b\n", include_str!("cepage_events.csv")]);
+        assert!(results[0].as_ref().unwrap().is_empty());
+        assert!(results[1].is_err());
+        assert!(results[2].as_ref().unwrap().is_empty());
+        let head_scope = project.timebases.id("head scope").unwrap();
+        assert_eq!(project.graph.get_time(head_scope, project.events.id("ext out").unwrap()), Some(35.0));
+    }
+
+    #[test]
+    fn import_reports_bad_rows() {
+        let mut project = Project::new();
+        let data = "timebase, event, time\nscope, trig, 0\nscope, trig, 5\nscope, light\nscope, light, soon\n\nscope, , 3\n";
+        let errors = project.import_csv(data).unwrap();
+        let lines: Vec<u64> = errors.iter().map(|error| error.line).collect();
+        assert_eq!(lines, vec![3, 4, 5, 7]);
+        assert_eq!(errors[0].message, "already entered");
+        assert_eq!(errors[2].message, "\"soon\" is not a number");
+        assert_eq!(project.graph.times().len(), 1);
+        assert!(project.import_csv("a, b\n").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut project = Project::new();
+        project.import_csv(include_str!("cepage_events.csv")).unwrap();
+        project.import_csv(include_str!("cepage_delays.csv")).unwrap();
+        project.add_event("unused").unwrap();
+        let restored = Project::from_json(&project.to_json()).unwrap();
+        assert_eq!(restored.to_data(), project.to_data());
+
+        let mut from_csv = Project::new();
+        assert!(from_csv.import_csv(&project.times_csv()).unwrap().is_empty());
+        assert!(from_csv.import_csv(&project.delays_csv()).unwrap().is_empty());
+        assert_eq!(from_csv.graph.times().len(), project.graph.times().len());
+
+        let mut data = project.to_data();
+        data.version = PROJECT_VERSION + 1;
+        assert!(matches!(Project::from_data(&data), Err(Errors::Unsupported)));
+        assert!(Project::from_json("{").is_err());
+    }
+
+    #[test]
+    fn links() {
+        let mut project = Project::new();
+        project.import_csv(include_str!("cepage_events.csv")).unwrap();
+        project.import_csv(include_str!("cepage_delays.csv")).unwrap();
+        let link = project.to_link();
+        assert!(link.starts_with("1."));
+        assert!(link.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)));
+        assert!(link.len() < project.to_json().len());
+        let restored = Project::from_link(&format!("#{}", link)).unwrap();
+        assert_eq!(restored.to_data(), project.to_data());
+
+        assert!(matches!(Project::from_link(&link.replacen('1', "2", 1)), Err(Errors::Unsupported)));
+        assert!(matches!(Project::from_link("1.AAAA"), Err(Errors::InvalidFile(_))));
+        assert!(matches!(Project::from_link("nonsense"), Err(Errors::InvalidFile(_))));
+
+        // a small link that would expand past the limit
+        let bomb = miniz_oxide::deflate::compress_to_vec(&vec![b' '; MAX_LINK_SIZE + 1], 9);
+        let bomb = format!("1.{}", URL_SAFE_NO_PAD.encode(bomb));
+        assert!(bomb.len() < 100_000);
+        assert!(matches!(Project::from_link(&bomb), Err(Errors::InvalidFile(message)) if message.contains("too much")));
+    }
+
+    #[test]
+    ///  0   ?
+    ///  |---|--->  experiment
+    ///   \0  \0
+    ///    |---|--->  scope
+    ///   100 200
+    fn derived_exports() {
+        let mut project = Project::new();
+        project.import_csv("timebase,event,time\nexperiment,start,0\nscope,start,100\nscope,light,200\n").unwrap();
+        project.import_csv("timebase_1,event_1,timebase_2,event_2,delay\nexperiment,start,scope,start,0\nexperiment,light,scope,light,0\n").unwrap();
+        project.add_timebase("unlinked").unwrap();
+        assert_eq!(project.time_matrix_csv(), "timebase,start,light\nexperiment,0,100\nscope,100,200\nunlinked,,\n");
+
+        let snippet = project.python_snippet();
+        assert!(snippet.contains("    \"experiment\": {\"start\": 0.0, \"light\": 100.0},\n"));
+        assert!(snippet.contains("    \"scope\": -100.0,\n"));
+        assert!(snippet.contains("    \"unlinked\": None,\n"));
+    }
+
+    #[test]
+    fn removing_clears_the_graph() {
+        let mut project = Project::with_size(2, 2);
+        let (tb_0, tb_1) = (project.timebases.id("timebase 0").unwrap(), project.timebases.id("timebase 1").unwrap());
+        let (e_0, e_1) = (project.events.id("event 0").unwrap(), project.events.id("event 1").unwrap());
+        project.graph.add_time(tb_0, e_0, 0.0).unwrap();
+        project.graph.add_time(tb_1, e_1, 10.0).unwrap();
+        project.graph.add_delay(tb_0, e_1, tb_1, e_1, 5.0).unwrap();
+        assert_eq!(project.graph.get_time(tb_0, e_1), None);
+
+        project.remove_event(e_0).unwrap();
+        assert_eq!(project.graph.times(), vec![(tb_1, e_1, 10.0)]);
+        project.remove_timebase(tb_1).unwrap();
+        assert!(project.graph.times().is_empty());
+        assert!(project.graph.delays().is_empty());
+        assert!(project.remove_timebase(tb_1).is_err());
+        assert_eq!(project.timebases.len(), 1);
+    }
+}
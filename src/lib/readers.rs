@@ -0,0 +1,373 @@
+//! Readers for the files scopes save, each giving a [`Waveform`] on its native time axis,
+//! usually in seconds. Use [`Waveform::scale_time`] to bring it into the units of the graph.
+
+use std::collections::BTreeMap;
+
+use crate::waveform::{Channel, TimeAxis, Waveform};
+use crate::Errors;
+
+fn invalid(message: &str) -> Errors {
+    Errors::InvalidFile(message.to_owned())
+}
+
+/// Reads fixed size numbers from a byte slice in either byte order.
+struct Bytes<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Bytes<'a> {
+    fn slice(&self, offset: usize, len: usize) -> Result<&'a [u8], Errors> {
+        let end = offset.checked_add(len).ok_or_else(|| invalid("offset out of range"))?;
+        self.data.get(offset..end).ok_or_else(|| invalid("file ends early"))
+    }
+
+    /// A length or offset stored as an `i32`, which must not be negative.
+    fn length(&self, offset: usize) -> Result<usize, Errors> {
+        usize::try_from(self.i32(offset)?).map_err(|_| invalid("negative length"))
+    }
+
+    /// Check `n` values of `size` bytes from `offset` are all in the file, before reading them.
+    fn check_array(&self, offset: usize, n: usize, size: usize) -> Result<(), Errors> {
+        let len = n.checked_mul(size).ok_or_else(|| invalid("array too long"))?;
+        self.slice(offset, len).map(|_| ())
+    }
+
+    fn array<const N: usize>(&self, offset: usize) -> Result<[u8; N], Errors> {
+        let mut bytes: [u8; N] = self.slice(offset, N)?.try_into().unwrap();
+        if !self.little_endian {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+
+    fn i16(&self, offset: usize) -> Result<i16, Errors> {
+        Ok(i16::from_le_bytes(self.array(offset)?))
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, Errors> {
+        Ok(u16::from_le_bytes(self.array(offset)?))
+    }
+
+    fn i32(&self, offset: usize) -> Result<i32, Errors> {
+        Ok(i32::from_le_bytes(self.array(offset)?))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, Errors> {
+        Ok(u32::from_le_bytes(self.array(offset)?))
+    }
+
+    fn f32(&self, offset: usize) -> Result<f32, Errors> {
+        Ok(f32::from_le_bytes(self.array(offset)?))
+    }
+
+    fn f64(&self, offset: usize) -> Result<f64, Errors> {
+        Ok(f64::from_le_bytes(self.array(offset)?))
+    }
+
+    /// Fixed width string padded with nulls.
+    fn text(&self, offset: usize, len: usize) -> Result<String, Errors> {
+        let bytes = self.slice(offset, len)?;
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(len);
+        Ok(String::from_utf8_lossy(&bytes[..end]).trim().to_owned())
+    }
+}
+
+/// Read a CSV export: optional `key, value` metadata lines, then an optional
+/// row of column names, then rows of numbers with time in the first column.
+pub fn read_csv(data: &str, timebase: usize) -> Result<Waveform, Errors> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data.as_bytes());
+
+    let mut metadata = BTreeMap::new();
+    let mut names: Option<Vec<String>> = None;
+    let mut times = Vec::new();
+    let mut columns: Vec<Vec<f64>> = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|error| Errors::InvalidFile(error.to_string()))?;
+        let cells: Vec<&str> = record.iter().filter(|cell| !cell.is_empty()).collect();
+        if cells.is_empty() {
+            continue
+        }
+        let numbers: Option<Vec<f64>> = cells.iter().map(|cell| cell.parse().ok()).collect();
+        match numbers {
+            Some(numbers) => {
+                if columns.is_empty() {
+                    columns = vec![Vec::new(); numbers.len() - 1];
+                } else if numbers.len() != columns.len() + 1 {
+                    return Err(Errors::LengthMismatch)
+                }
+                times.push(numbers[0]);
+                for (column, value) in columns.iter_mut().zip(&numbers[1..]) {
+                    column.push(*value);
+                }
+            },
+            None if !times.is_empty() => return Err(invalid("text among the data rows")),
+            None => {
+                // a row of names just before the data, anything earlier is metadata
+                if let Some(previous) = names.take() {
+                    if previous.len() == 2 {
+                        metadata.insert(previous[0].clone(), previous[1].clone());
+                    }
+                }
+                names = Some(cells.iter().map(|cell| cell.to_string()).collect());
+            },
+        }
+    }
+    if times.is_empty() {
+        return Err(invalid("no data rows"))
+    }
+
+    let channels = columns.into_iter().enumerate().map(|(i, values)| {
+        let name = names.as_ref()
+            .and_then(|names| names.get(i + 1).cloned())
+            .unwrap_or_else(|| format!("channel {}", i + 1));
+        Channel {name, values}
+    }).collect();
+    let mut waveform = Waveform::new(timebase, TimeAxis::Explicit(times), channels)?;
+    waveform.metadata = metadata;
+    Ok(waveform)
+}
+
+/// Find a Tektronix header value by its long or short form, ignoring any `:WFMPRE:` style prefix.
+fn tek_value<'a>(header: &'a BTreeMap<String, String>, long: &str, short: &str) -> Option<&'a str> {
+    header.iter()
+        .find(|(key, _)| key.as_str() == long || key.as_str() == short)
+        .map(|(_, value)| value.as_str())
+}
+
+fn tek_number(header: &BTreeMap<String, String>, long: &str, short: &str) -> Result<f64, Errors> {
+    tek_value(header, long, short)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| Errors::InvalidFile(format!("missing {}", long)))
+}
+
+/// Read a Tektronix `.isf` file: a `WFMPRE` header followed by a `CURVE` block.
+pub fn read_isf(data: &[u8], timebase: usize) -> Result<Waveform, Errors> {
+    let curve = data.windows(5).position(|window| window == b"CURVE").ok_or_else(|| invalid("no CURVE"))?;
+    let header_text = String::from_utf8_lossy(&data[..curve]);
+    let mut header = BTreeMap::new();
+    for field in header_text.split(';') {
+        let field = field.trim();
+        let Some((key, value)) = field.split_once(' ') else {
+            continue
+        };
+        let key = key.rsplit(':').next().unwrap().to_uppercase();
+        header.insert(key, value.trim().trim_matches('"').to_owned());
+    }
+
+    let n_points = tek_number(&header, "NR_PT", "NR_P")? as usize;
+    let x_incr = tek_number(&header, "XINCR", "XIN")?;
+    let x_zero = tek_number(&header, "XZERO", "XZE")?;
+    let pt_off = tek_number(&header, "PT_OFF", "PT_O").unwrap_or(0.0);
+    let y_mult = tek_number(&header, "YMULT", "YMU")?;
+    let y_off = tek_number(&header, "YOFF", "YOF").unwrap_or(0.0);
+    let y_zero = tek_number(&header, "YZERO", "YZE").unwrap_or(0.0);
+    let encoding = tek_value(&header, "ENCDG", "ENC").unwrap_or("BIN").to_uppercase();
+
+    let after = &data[curve + "CURVE".len()..];
+    let raw: Vec<f64> = if encoding.starts_with("ASC") {
+        String::from_utf8_lossy(after)
+            .trim()
+            .split(',')
+            .take(n_points)
+            .map(|value| value.trim().parse().map_err(|_| invalid("bad ASCII point")))
+            .collect::<Result<_, _>>()?
+    } else {
+        let bytes_per_point = tek_number(&header, "BYT_NR", "BYT_N")? as usize;
+        let signed = tek_value(&header, "BN_FMT", "BN_F").unwrap_or("RI").to_uppercase().starts_with("RI");
+        let little_endian = tek_value(&header, "BYT_OR", "BYT_O").unwrap_or("MSB").to_uppercase().starts_with("LSB");
+        // IEEE 488.2 definite length block: '#', digit count, byte count, bytes
+        let hash = after.iter().position(|&b| b == b'#').ok_or_else(|| invalid("no data block"))?;
+        let n_digits = (after.get(hash + 1).copied().ok_or_else(|| invalid("no data block"))? as char)
+            .to_digit(10).ok_or_else(|| invalid("bad data block"))? as usize;
+        let start = hash + 2 + n_digits;
+        let bytes = Bytes {data: after.get(start..).ok_or_else(|| invalid("file ends early"))?, little_endian};
+        (0..n_points).map(|i| {
+            let offset = i * bytes_per_point;
+            Ok(match (bytes_per_point, signed) {
+                (1, true) => bytes.slice(offset, 1)?[0] as i8 as f64,
+                (1, false) => bytes.slice(offset, 1)?[0] as f64,
+                (2, true) => bytes.i16(offset)? as f64,
+                (2, false) => bytes.u16(offset)? as f64,
+                _ => return Err(Errors::Unsupported),
+            })
+        }).collect::<Result<_, _>>()?
+    };
+    if raw.len() != n_points {
+        return Err(Errors::LengthMismatch)
+    }
+
+    let name = tek_value(&header, "WFID", "WFI")
+        .and_then(|id| id.split(',').next())
+        .unwrap_or("CH1")
+        .trim()
+        .to_owned();
+    let values = raw.iter().map(|raw| (raw - y_off) * y_mult + y_zero).collect();
+    let time = TimeAxis::Uniform {t0: x_zero - pt_off * x_incr, dt: x_incr, len: n_points};
+    let mut waveform = Waveform::new(timebase, time, vec![Channel {name, values}])?;
+    waveform.metadata = header;
+    Ok(waveform)
+}
+
+/// Read a Tektronix `.wfm` file, version `WFM#003`, using the first frame of the curve.
+/// The sub-sample trigger position is not applied.
+pub fn read_wfm(data: &[u8], timebase: usize) -> Result<Waveform, Errors> {
+    let little_endian = match data.get(0..2) {
+        Some([0x0f, 0x0f]) => true,
+        Some([0xf0, 0xf0]) => false,
+        _ => return Err(invalid("not a WFM file")),
+    };
+    let bytes = Bytes {data, little_endian};
+    let version = bytes.text(2, 8)?;
+    if version != ":WFM#003" {
+        return Err(Errors::Unsupported)
+    }
+    let bytes_per_point = bytes.slice(15, 1)?[0] as usize;
+    let curve_offset = bytes.u32(16)? as usize;
+    let v_scale = bytes.f64(168)?;
+    let v_offset = bytes.f64(176)?;
+    let format = bytes.i32(240)?;
+    let t_scale = bytes.f64(488)?;
+    let t_start = bytes.f64(496)?;
+    let data_start = bytes.u32(822)? as usize;
+    let data_end = bytes.u32(826)? as usize;
+
+    let n_points = data_end.saturating_sub(data_start) / bytes_per_point.max(1);
+    let first = curve_offset.checked_add(data_start).ok_or_else(|| invalid("offset out of range"))?;
+    bytes.check_array(first, n_points, bytes_per_point)?;
+    let raw: Vec<f64> = (0..n_points).map(|i| {
+        let offset = first + i * bytes_per_point;
+        Ok(match (format, bytes_per_point) {
+            (0, 2) => bytes.i16(offset)? as f64,
+            (1, 4) => bytes.i32(offset)? as f64,
+            (2, 4) => bytes.u32(offset)? as f64,
+            (4, 4) => bytes.f32(offset)? as f64,
+            (5, 8) => bytes.f64(offset)?,
+            (6, 1) => bytes.slice(offset, 1)?[0] as f64,
+            (7, 1) => bytes.slice(offset, 1)?[0] as i8 as f64,
+            _ => return Err(Errors::Unsupported),
+        })
+    }).collect::<Result<_, _>>()?;
+
+    let values = raw.iter().map(|raw| raw * v_scale + v_offset).collect();
+    let time = TimeAxis::Uniform {t0: t_start, dt: t_scale, len: n_points};
+    let mut waveform = Waveform::new(timebase, time, vec![Channel {name: "CH1".to_owned(), values}])?;
+    waveform.metadata.insert("version".to_owned(), version);
+    Ok(waveform)
+}
+
+/// Read a LeCroy `.trc` file, as saved in binary by the scope, using the `WAVEDESC` block.
+pub fn read_trc(data: &[u8], timebase: usize) -> Result<Waveform, Errors> {
+    let start = data.windows(8).position(|window| window == b"WAVEDESC").ok_or_else(|| invalid("no WAVEDESC"))?;
+    let data = &data[start..];
+    // COMM_ORDER is 0 for big endian, 1 for little endian, and reads as 0 or 1 either way round
+    let little_endian = data.get(34..36).map(|order| order != [0, 0]).unwrap_or(false);
+    let bytes = Bytes {data, little_endian};
+
+    let word = bytes.i16(32)? == 1;
+    let wave_descriptor = bytes.length(36)?;
+    let user_text = bytes.length(40)?;
+    let trigtime_array = bytes.length(48)?;
+    let ris_time_array = bytes.length(52)?;
+    let res_array_1 = bytes.length(56)?;
+    let wave_array_1 = bytes.length(60)?;
+    let trace_label = bytes.text(96, 16)?;
+    let vertical_gain = bytes.f32(156)? as f64;
+    let vertical_offset = bytes.f32(160)? as f64;
+    let horiz_interval = bytes.f32(176)? as f64;
+    let horiz_offset = bytes.f64(180)?;
+    let wave_source = bytes.i16(344)?;
+
+    let first = [user_text, trigtime_array, ris_time_array, res_array_1].into_iter()
+        .try_fold(wave_descriptor, usize::checked_add)
+        .ok_or_else(|| invalid("offset out of range"))?;
+    let bytes_per_point = if word { 2 } else { 1 };
+    let n_points = wave_array_1 / bytes_per_point;
+    bytes.check_array(first, n_points, bytes_per_point)?;
+    let raw: Vec<f64> = (0..n_points).map(|i| {
+        let offset = first + i * bytes_per_point;
+        Ok(if word { bytes.i16(offset)? as f64 } else { bytes.slice(offset, 1)?[0] as i8 as f64 })
+    }).collect::<Result<_, _>>()?;
+
+    let name = if trace_label.is_empty() { format!("C{}", wave_source + 1) } else { trace_label };
+    let values = raw.iter().map(|raw| vertical_gain * raw - vertical_offset).collect();
+    let time = TimeAxis::Uniform {t0: horiz_offset, dt: horiz_interval, len: n_points};
+    let mut waveform = Waveform::new(timebase, time, vec![Channel {name, values}])?;
+    waveform.metadata.insert("instrument".to_owned(), bytes.text(76, 16)?);
+    waveform.metadata.insert("template".to_owned(), bytes.text(16, 16)?);
+    Ok(waveform)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generic_csv() {
+        let waveform = read_csv(include_str!("fixtures/scope.csv"), 2).unwrap();
+        assert_eq!(waveform.metadata["Model"], "DPO4104");
+        assert_eq!(waveform.metadata["Sample Interval"], "1e-09");
+        assert_eq!(waveform.time.times(), vec![-2e-9, -1e-9, 0.0, 1e-9]);
+        assert_eq!(waveform.channels[0].name, "CH1");
+        assert_eq!(waveform.channels[1].values, vec![0.0, 0.5, 1.0, 1.5]);
+    }
+
+    #[test]
+    fn csv_without_header() {
+        let waveform = read_csv("0, 1\n1, 2\n", 0).unwrap();
+        assert_eq!(waveform.channels[0].name, "channel 1");
+        assert!(read_csv("0, 1\n1\n", 0).is_err());
+        assert!(read_csv("no data\n", 0).is_err());
+    }
+
+    #[test]
+    fn tektronix_isf() {
+        let waveform = read_isf(include_bytes!("fixtures/scope.isf"), 1).unwrap();
+        assert_eq!(waveform.channels[0].name, "Ch1");
+        assert_eq!(waveform.time, TimeAxis::Uniform {t0: -2e-6, dt: 4e-10, len: 4});
+        assert_eq!(waveform.channels[0].values, vec![-0.5, 0.0, 0.25, 1.0]);
+    }
+
+    #[test]
+    fn tektronix_wfm() {
+        let waveform = read_wfm(include_bytes!("fixtures/scope.wfm"), 1).unwrap();
+        assert_eq!(waveform.time, TimeAxis::Uniform {t0: -1e-6, dt: 1e-9, len: 4});
+        assert_eq!(waveform.channels[0].values, vec![-0.5, 0.0, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn lecroy_trc() {
+        let waveform = read_trc(include_bytes!("fixtures/scope.trc"), 3).unwrap();
+        assert_eq!(waveform.channels[0].name, "C2");
+        assert_eq!(waveform.metadata["instrument"], "LECROYWR104MXi");
+        assert_eq!(waveform.time, TimeAxis::Uniform {t0: -5e-7, dt: 1e-9f32 as f64, len: 4});
+        assert_eq!(waveform.channels[0].values, vec![-0.25, 0.0, 0.25, 0.5]);
+    }
+
+    #[test]
+    fn corrupt_trc() {
+        let file = include_bytes!("fixtures/scope.trc");
+        assert!(matches!(read_trc(&file[..file.len() - 1], 3), Err(Errors::InvalidFile(_))));
+
+        // WAVE_DESCRIPTOR of -1 and of i32::MAX
+        let start = file.windows(8).position(|window| window == b"WAVEDESC").unwrap();
+        let mut negative = file.to_vec();
+        negative[start + 36..start + 40].copy_from_slice(&(-1i32).to_le_bytes());
+        assert!(matches!(read_trc(&negative, 3), Err(Errors::InvalidFile(message)) if message == "negative length"));
+        let mut huge = file.to_vec();
+        huge[start + 36..start + 40].copy_from_slice(&i32::MAX.to_le_bytes());
+        huge[start + 60..start + 64].copy_from_slice(&i32::MAX.to_le_bytes());
+        assert!(matches!(read_trc(&huge, 3), Err(Errors::InvalidFile(_))));
+    }
+
+    #[test]
+    fn wrong_format() {
+        assert!(read_trc(b"not a scope file", 0).is_err());
+        assert!(read_wfm(b"not a scope file", 0).is_err());
+        assert!(read_isf(b"not a scope file", 0).is_err());
+    }
+}
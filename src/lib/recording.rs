@@ -0,0 +1,145 @@
+use crate::bounds::Interval;
+use crate::{DelayGraph, Errors, Event};
+
+/// The span a detector records for, set by its record length and pretrigger fraction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecordWindow {
+    pub length: f64,
+    /// Fraction of the record taken before the trigger, between 0 and 1.
+    pub pretrigger: f64,
+    /// Event the detector triggers on, `Event::T0` when its timebase starts at the trigger.
+    pub trigger: Event<usize>,
+}
+
+impl RecordWindow {
+    pub fn new(length: f64, pretrigger: f64) -> Self {
+        Self {length, pretrigger, trigger: Event::T0}
+    }
+
+    pub fn triggered_by(self, event: usize) -> Self {
+        Self {trigger: Event::Event(event), ..self}
+    }
+
+    /// Start and end of the record for a trigger at `trigger_time`.
+    pub fn span(&self, trigger_time: f64) -> Interval {
+        let start = trigger_time - self.pretrigger * self.length;
+        Interval::new(start, start + self.length)
+    }
+}
+
+/// Whether one event of interest was recorded by one detector.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowCheck {
+    pub timebase: usize,
+    pub event: usize,
+    /// Time of the event on the detector timebase, if it can be found.
+    pub time: Option<f64>,
+    /// Recorded span on the detector timebase, if its trigger time can be found.
+    pub window: Option<Interval>,
+    /// Distance to the nearest end of the record, negative when outside it.
+    pub margin: Option<f64>,
+}
+
+impl WindowCheck {
+    pub fn is_inside(&self) -> Option<bool> {
+        self.margin.map(|margin| margin >= 0.0)
+    }
+}
+
+impl DelayGraph {
+
+    pub fn set_record_window(&mut self, timebase: usize, window: RecordWindow) -> Result<(), Errors> {
+        if !window.length.is_finite() || window.length < 0.0 || !(0.0..=1.0).contains(&window.pretrigger) {
+            return Err(Errors::InvalidBound)
+        }
+        self.windows.insert(timebase, window);
+        Ok(())
+    }
+
+    pub fn remove_record_window(&mut self, timebase: usize) -> Result<(), Errors> {
+        self.windows.remove(&timebase).map(|_| ()).ok_or(Errors::NotFound)
+    }
+
+    pub fn lookup_record_window(&self, timebase: usize) -> Option<&RecordWindow> {
+        self.windows.get(&timebase)
+    }
+
+    /// Span recorded on `timebase`, in its own time.
+    pub fn record_span(&self, timebase: usize) -> Option<Interval> {
+        let window = self.windows.get(&timebase)?;
+        let trigger_time = match window.trigger {
+            Event::T0 => 0.0,
+            Event::Event(event) => self.get_time(timebase, event)?,
+        };
+        Some(window.span(trigger_time))
+    }
+
+    /// Check every event in `events` against every detector with a record window.
+    pub fn check_windows(&self, events: &[usize]) -> Vec<WindowCheck> {
+        let mut checks = Vec::new();
+        for &timebase in self.windows.keys() {
+            let window = self.record_span(timebase);
+            for &event in events {
+                let time = self.get_time(timebase, event);
+                let margin = match (time, window) {
+                    (Some(time), Some(window)) => Some((time - window.min).min(window.max - time)),
+                    _ => None,
+                };
+                checks.push(WindowCheck {timebase, event, time, window, margin});
+            }
+        }
+        checks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    ///     -200         0          800
+    ///  |----[----------|-----------]---->  scope
+    ///           ^ 100       ^ 700  ^ 900
+    fn events_inside_and_outside_window() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 100.0).unwrap();
+        event_graph.add_time(1, 2, 700.0).unwrap();
+        event_graph.add_time(1, 3, 900.0).unwrap();
+        for length in [f64::NAN, f64::INFINITY, -1.0] {
+            assert!(event_graph.set_record_window(1, RecordWindow::new(length, 0.0)).is_err());
+        }
+        event_graph.set_record_window(1, RecordWindow::new(1000.0, 0.2)).unwrap();
+
+        let checks = event_graph.check_windows(&[1, 2, 3, 4]);
+        let margins: Vec<Option<f64>> = checks.iter().map(|check| check.margin).collect();
+        assert_eq!(margins, vec![Some(300.0), Some(100.0), Some(-100.0), None]);
+        assert_eq!(checks[2].is_inside(), Some(false));
+        assert_eq!(checks[3].is_inside(), None);
+    }
+
+    #[test]
+    fn window_follows_trigger_event() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 0.0).unwrap();
+        event_graph.add_time(2, 0, -895.0).unwrap();
+        event_graph.add_time(2, 1, 2500.0).unwrap();
+        event_graph.add_delay(1, 1, 2, 1, 65.5).unwrap();
+        event_graph.set_record_window(2, RecordWindow::new(2000.0, 0.5).triggered_by(0)).unwrap();
+
+        assert_eq!(event_graph.record_span(2), Some(Interval::new(-1895.0, 105.0)));
+        let checks = event_graph.check_windows(&[1]);
+        assert_eq!(checks[0].is_inside(), Some(false));
+    }
+
+    #[test]
+    fn window_removed_with_its_trigger() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 0, 0.0).unwrap();
+        event_graph.add_time(2, 0, 0.0).unwrap();
+        event_graph.set_record_window(1, RecordWindow::new(1000.0, 0.2).triggered_by(0)).unwrap();
+        event_graph.set_record_window(2, RecordWindow::new(1000.0, 0.2)).unwrap();
+        event_graph.remove_event(0);
+        assert!(event_graph.lookup_record_window(1).is_none());
+        assert!(event_graph.lookup_record_window(2).is_some());
+    }
+}
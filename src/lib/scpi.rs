@@ -0,0 +1,90 @@
+use crate::generator::{DelayGenerator, Reference};
+use crate::Errors;
+
+/// Command set of the instrument a script is written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptFormat {
+    /// SRS DG645 style, `DLAY c,d,t` with outputs A to H as channels 2 to 9.
+    Dg645,
+    /// BNC 575 style, one `:PULSE<n>` block per channel.
+    Bnc575,
+}
+
+impl ScriptFormat {
+    fn max_channels(&self) -> usize {
+        match self {
+            ScriptFormat::Dg645 => 8,
+            ScriptFormat::Bnc575 => 8,
+        }
+    }
+}
+
+/// Seconds written with picosecond precision, which is finer than either instrument can set.
+fn seconds(delay: f64, seconds_per_unit: f64) -> String {
+    format!("{:.12}", delay * seconds_per_unit)
+}
+
+/// Write the channel settings of `generator` as a command script, one command per line.
+/// `seconds_per_unit` converts the graph units to seconds, e.g. `1e-9` for nanoseconds.
+pub fn script(generator: &DelayGenerator, format: ScriptFormat, seconds_per_unit: f64) -> Result<String, Errors> {
+    if generator.channels.len() > format.max_channels() {
+        return Err(Errors::Unsupported)
+    }
+    let mut lines = Vec::new();
+    for (index, channel) in generator.channels.iter().enumerate() {
+        let delay = seconds(channel.delay, seconds_per_unit);
+        match format {
+            ScriptFormat::Dg645 => {
+                let reference = match channel.reference {
+                    Reference::T0 => 0,
+                    Reference::Channel(reference) => reference + 2,
+                };
+                lines.push(format!("DLAY {},{},{}", index + 2, reference, delay));
+            },
+            ScriptFormat::Bnc575 => {
+                let reference = match channel.reference {
+                    Reference::T0 => "T0".to_owned(),
+                    Reference::Channel(reference) => format!("CH{}", (b'A' + reference as u8) as char),
+                };
+                lines.push(format!(":PULSE{}:SYNC {}", index + 1, reference));
+                lines.push(format!(":PULSE{}:DELAY {}", index + 1, delay));
+            },
+        }
+    }
+    Ok(lines.iter().map(|line| format!("{}\n", line)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generator() -> DelayGenerator {
+        let mut generator = DelayGenerator::new(0, 0, 0.005).unwrap();
+        let a = generator.add_channel("A", 1, Reference::T0, 100.0).unwrap();
+        generator.add_channel("B", 2, Reference::Channel(a), 50.0).unwrap();
+        generator.add_channel("C", 3, Reference::T0, 1234.565).unwrap();
+        generator.set_insertion_delay(1, 12.5).unwrap();
+        generator
+    }
+
+    #[test]
+    fn dg645_script() {
+        let script = script(&generator(), ScriptFormat::Dg645, 1e-9).unwrap();
+        assert_eq!(script, include_str!("golden/dg645.txt"));
+    }
+
+    #[test]
+    fn bnc575_script() {
+        let script = script(&generator(), ScriptFormat::Bnc575, 1e-9).unwrap();
+        assert_eq!(script, include_str!("golden/bnc575.txt"));
+    }
+
+    #[test]
+    fn too_many_channels() {
+        let mut generator = DelayGenerator::new(0, 0, 1.0).unwrap();
+        for event in 1..10 {
+            generator.add_channel("X", event, Reference::T0, 0.0).unwrap();
+        }
+        assert!(script(&generator, ScriptFormat::Dg645, 1e-9).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{DelayGraph, Event, Quantity};
+
+/// How strongly a derived quantity depends on one entered time or delay.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sensitivity {
+    pub input: Quantity,
+    /// Entered value of the input.
+    pub value: f64,
+    /// Change in the derived quantity per unit change of the input.
+    pub derivative: f64,
+}
+
+/// Sensitivities ranked from strongest to weakest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensitivityReport {
+    pub target: Quantity,
+    pub sensitivities: Vec<Sensitivity>,
+}
+
+impl SensitivityReport {
+    /// Inputs the target actually depends on.
+    pub fn dependencies(&self) -> impl Iterator<Item = &Sensitivity> {
+        self.sensitivities.iter().filter(|sensitivity| sensitivity.derivative != 0.0)
+    }
+
+    /// Report as CSV, one row per entered time or delay.
+    pub fn to_csv(&self) -> String {
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(["kind", "timebase_1", "event_1", "timebase_2", "event_2", "value", "derivative"]).unwrap();
+        for sensitivity in &self.sensitivities {
+            let Quantity {from, to} = sensitivity.input;
+            let event = |event: Event<usize>| match event {
+                Event::Event(event) => event.to_string(),
+                Event::T0 => "T0".to_owned(),
+            };
+            let kind = if sensitivity.input.is_time() { "time" } else { "delay" };
+            writer.write_record([
+                kind.to_owned(),
+                from.timebase.to_string(),
+                event(from.event),
+                to.timebase.to_string(),
+                event(to.event),
+                sensitivity.value.to_string(),
+                sensitivity.derivative.to_string(),
+            ]).unwrap();
+        }
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+}
+
+impl DelayGraph {
+
+    /// Partial derivative of `target` with respect to every entered time and delay.
+    ///
+    /// Every link only adds an offset, so an input on the path of the target
+    /// counts +1 when the path runs the way it was entered and -1 when it runs
+    /// against it, and every other input counts 0.
+    pub fn sensitivity(&self, target: &Quantity) -> Option<SensitivityReport> {
+        let path = self.path(target.from.timebase, target.from.event, target.to.timebase, target.to.event)?;
+        let inputs = self.times().into_iter()
+            .map(|(timebase, event, value)| (Quantity::time(timebase, event), value))
+            .chain(self.delays().into_iter()
+                .map(|(timebase_1, event_1, timebase_2, event_2, value)| (Quantity::delay(timebase_1, event_1, timebase_2, event_2), value)));
+
+        let mut sensitivities: Vec<Sensitivity> = inputs.map(|(input, value)| {
+            let derivative = path.windows(2).map(|pair| {
+                if (pair[0], pair[1]) == (input.from, input.to) {
+                    1.0
+                } else if (pair[1], pair[0]) == (input.from, input.to) {
+                    -1.0
+                } else {
+                    0.0
+                }
+            }).sum();
+            Sensitivity {input, value, derivative}
+        }).collect();
+        sensitivities.sort_by(|a, b| b.derivative.abs().total_cmp(&a.derivative.abs()));
+        Some(SensitivityReport {target: *target, sensitivities})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    ///  0   ?
+    ///  |---|--->
+    ///   \0  \0
+    ///    |---|--->
+    ///   100 200
+    fn derivatives_follow_the_path() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 0.0).unwrap();
+        event_graph.add_time(2, 1, 100.0).unwrap();
+        event_graph.add_time(2, 2, 200.0).unwrap();
+        event_graph.add_time(3, 1, 50.0).unwrap();
+        event_graph.add_delay(1, 1, 2, 1, 0.0).unwrap();
+        event_graph.add_delay(2, 2, 1, 2, 0.0).unwrap();
+
+        let report = event_graph.sensitivity(&Quantity::time(1, 2)).unwrap();
+        let derivative = |input: Quantity| report.sensitivities.iter().find(|s| s.input == input).unwrap().derivative;
+        assert_eq!(derivative(Quantity::time(1, 1)), 1.0);
+        assert_eq!(derivative(Quantity::time(2, 1)), -1.0);
+        assert_eq!(derivative(Quantity::time(2, 2)), 1.0);
+        assert_eq!(derivative(Quantity::delay(1, 1, 2, 1)), 1.0);
+        // entered from timebase 2, the way the path runs
+        assert_eq!(derivative(Quantity::delay(2, 2, 1, 2)), 1.0);
+        assert_eq!(derivative(Quantity::time(3, 1)), 0.0);
+        assert_eq!(report.dependencies().count(), 5);
+        assert_eq!(report.sensitivities.last().unwrap().input, Quantity::time(3, 1));
+
+        let csv = report.to_csv();
+        assert!(csv.starts_with("kind,timebase_1,event_1,timebase_2,event_2,value,derivative\n"));
+        assert!(csv.contains("time,3,T0,3,1,50,0\n"));
+    }
+
+    #[test]
+    fn underivable_target() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 0.0).unwrap();
+        assert!(event_graph.sensitivity(&Quantity::time(2, 1)).is_none());
+    }
+}
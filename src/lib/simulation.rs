@@ -0,0 +1,358 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, VecDeque};
+
+use crate::generator::DelayGenerator;
+use crate::{DelayGraph, Errors, Event, TimebaseEventKey};
+
+pub type DeviceId = usize;
+
+/// A piece of the trigger distribution, acting on the pulses arriving at its inputs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Component {
+    /// Master trigger, fires when the simulation starts.
+    Master,
+    /// One output of a delay generator, firing `delay` after its trigger input.
+    DelayChannel { delay: f64 },
+    FanOut { delay: f64 },
+    Cable { delay: f64 },
+    /// Fires `delay` after the last of its inputs arrives.
+    And { delay: f64 },
+    /// Fires `delay` after the first of its inputs arrives.
+    Or { delay: f64 },
+    /// End of the chain, e.g. a scope trigger input, with its own trigger delay.
+    Detector { delay: f64 },
+}
+
+impl Component {
+    fn delay(&self) -> f64 {
+        match *self {
+            Component::Master => 0.0,
+            Component::DelayChannel { delay }
+            | Component::FanOut { delay }
+            | Component::Cable { delay }
+            | Component::And { delay }
+            | Component::Or { delay }
+            | Component::Detector { delay } => delay,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    pub name: String,
+    pub component: Component,
+    pub inputs: Vec<DeviceId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Firing {
+    pub device: DeviceId,
+    pub time: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Issue {
+    /// The device output would come before the input that causes it.
+    FiresBeforeInput { device: DeviceId, input_time: f64, fire_time: f64 },
+    /// No pulse, or not enough pulses for an `And`, ever reach the device.
+    NeverFires { device: DeviceId },
+}
+
+/// Result of a simulation: when each device fired, in time order, and anything suspicious.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeline {
+    pub firings: Vec<Firing>,
+    pub issues: Vec<Issue>,
+}
+
+impl Timeline {
+    pub fn time(&self, device: DeviceId) -> Option<f64> {
+        self.firings.iter().find(|firing| firing.device == device).map(|firing| firing.time)
+    }
+
+    /// Write the firing times into `graph` as times on `timebase`,
+    /// with `events` pairing each device of interest with its event.
+    pub fn add_to_graph(&self, graph: &mut DelayGraph, timebase: usize, events: &[(DeviceId, usize)]) -> Result<(), Errors> {
+        for &(device, event) in events {
+            let time = self.time(device).ok_or(Errors::NotFound)?;
+            graph.update_time(timebase, event, time)?;
+        }
+        Ok(())
+    }
+}
+
+/// A pulse arriving at a device input, ordered so the earliest comes out of the heap first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Pulse {
+    time: f64,
+    device: DeviceId,
+    from: DeviceId,
+}
+
+impl Eq for Pulse {}
+
+impl Ord for Pulse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.time.total_cmp(&self.time)
+            .then_with(|| other.device.cmp(&self.device))
+            .then_with(|| other.from.cmp(&self.from))
+    }
+}
+
+impl PartialOrd for Pulse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A trigger distribution network of devices wired output to input.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Simulation {
+    pub devices: Vec<Device>,
+}
+
+impl Simulation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: &str, component: Component) -> DeviceId {
+        self.devices.push(Device {name: name.to_owned(), component, inputs: Vec::new()});
+        self.devices.len() - 1
+    }
+
+    /// Wire the output of `from` to an input of `to`.
+    pub fn connect(&mut self, from: DeviceId, to: DeviceId) -> Result<(), Errors> {
+        if from >= self.devices.len() || to >= self.devices.len() {
+            return Err(Errors::NotFound)
+        }
+        if self.devices[to].component == Component::Master {
+            return Err(Errors::Unsupported)
+        }
+        if self.devices[to].inputs.contains(&from) {
+            return Err(Errors::AlreadyExists)
+        }
+        self.devices[to].inputs.push(from);
+        Ok(())
+    }
+
+    /// Add one device per channel of `generator`, all triggered by `input`.
+    /// Channel outputs fire at their output time, including insertion delays.
+    pub fn add_generator(&mut self, generator: &DelayGenerator, input: DeviceId) -> Result<Vec<DeviceId>, Errors> {
+        let mut ids = Vec::new();
+        for (index, channel) in generator.channels.iter().enumerate() {
+            let delay = generator.output_time(index).unwrap();
+            let id = self.add(&channel.name, Component::DelayChannel { delay });
+            self.connect(input, id)?;
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+
+    /// Network following the entered delays out from `event` on `timebase`, the master trigger.
+    /// Every event reached becomes a device fired by the one before it, a delay channel if the link
+    /// is one of the graph's channels and a cable otherwise, and the devices are returned by event.
+    pub fn from_graph(graph: &DelayGraph, timebase: usize, event: usize) -> Result<(Self, BTreeMap<TimebaseEventKey, DeviceId>), Errors> {
+        let master = TimebaseEventKey::new(timebase, event);
+        if !graph.graph.contains_node(master) {
+            return Err(Errors::NotFound)
+        }
+        let mut simulation = Self::new();
+        let mut devices = BTreeMap::from([(master, simulation.add(&master.to_string(), Component::Master))]);
+        let mut queue = VecDeque::from([master]);
+        // entries never close a loop, so every event is reached along one chain of delays
+        while let Some(from) = queue.pop_front() {
+            // times link events through T0, which no pulse travels along
+            let next: Vec<_> = graph.graph.neighbors(from)
+                .filter(|to| matches!(to.event, Event::Event(_)) && !devices.contains_key(to))
+                .collect();
+            for to in next {
+                let delay = graph.graph[(from, to)];
+                let component = if graph.channels.contains_key(&(from, to)) || graph.channels.contains_key(&(to, from)) {
+                    Component::DelayChannel { delay }
+                } else {
+                    Component::Cable { delay }
+                };
+                let id = simulation.add(&to.to_string(), component);
+                simulation.connect(devices[&from], id)?;
+                devices.insert(to, id);
+                queue.push_back(to);
+            }
+        }
+        Ok((simulation, devices))
+    }
+
+    /// Propagate the master triggers, fired at `start`, through the network.
+    /// Every device fires at most once, and one that would fire before its input
+    /// is flagged and passes nothing on, so pulses are always handled in time order.
+    pub fn run(&self, start: f64) -> Timeline {
+        let mut outputs: HashMap<DeviceId, Vec<DeviceId>> = HashMap::new();
+        for (id, device) in self.devices.iter().enumerate() {
+            for &input in &device.inputs {
+                outputs.entry(input).or_default().push(id);
+            }
+        }
+
+        let mut pending = BinaryHeap::new();
+        let mut arrived: HashMap<DeviceId, Vec<DeviceId>> = HashMap::new();
+        let mut firings = Vec::new();
+        let mut issues = Vec::new();
+
+        let fire = |device: DeviceId, time: f64, pending: &mut BinaryHeap<Pulse>, firings: &mut Vec<Firing>| {
+            firings.push(Firing {device, time});
+            for &next in outputs.get(&device).into_iter().flatten() {
+                pending.push(Pulse {time, device: next, from: device});
+            }
+        };
+
+        for (id, device) in self.devices.iter().enumerate() {
+            if device.component == Component::Master {
+                fire(id, start, &mut pending, &mut firings);
+            }
+        }
+
+        while let Some(Pulse {time, device, from}) = pending.pop() {
+            if firings.iter().any(|firing| firing.device == device) {
+                continue
+            }
+            let inputs = arrived.entry(device).or_default();
+            if !inputs.contains(&from) {
+                inputs.push(from);
+            }
+            let component = self.devices[device].component;
+            let ready = match component {
+                Component::And { .. } => inputs.len() == self.devices[device].inputs.len(),
+                _ => true,
+            };
+            if ready {
+                let fire_time = time + component.delay();
+                if fire_time < time {
+                    // its pulses would arrive before ones already handled
+                    issues.push(Issue::FiresBeforeInput {device, input_time: time, fire_time});
+                    firings.push(Firing {device, time: fire_time});
+                } else {
+                    fire(device, fire_time, &mut pending, &mut firings);
+                }
+            }
+        }
+
+        for (id, _) in self.devices.iter().enumerate() {
+            if !firings.iter().any(|firing| firing.device == id) {
+                issues.push(Issue::NeverFires {device: id});
+            }
+        }
+        firings.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Timeline {firings, issues}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::Reference;
+
+    #[test]
+    /// master -> cable 10 -> generator A 100 -> fan out 2 -> scope 1, scope 2 via cable 5
+    ///                     \-> generator B 150 -/
+    fn fan_out_and_logic() {
+        let mut simulation = Simulation::new();
+        let master = simulation.add("master", Component::Master);
+        let cable = simulation.add("cable", Component::Cable { delay: 10.0 });
+        simulation.connect(master, cable).unwrap();
+
+        let mut generator = DelayGenerator::new(0, 0, 1.0).unwrap();
+        generator.add_channel("A", 1, Reference::T0, 100.0).unwrap();
+        generator.add_channel("B", 2, Reference::Channel(0), 50.0).unwrap();
+        let channels = simulation.add_generator(&generator, cable).unwrap();
+
+        let fan_out = simulation.add("fan out", Component::FanOut { delay: 2.0 });
+        simulation.connect(channels[0], fan_out).unwrap();
+        let scope_1 = simulation.add("scope 1", Component::Detector { delay: 0.0 });
+        simulation.connect(fan_out, scope_1).unwrap();
+        let cable_2 = simulation.add("cable 2", Component::Cable { delay: 5.0 });
+        simulation.connect(fan_out, cable_2).unwrap();
+
+        let both = simulation.add("and", Component::And { delay: 1.0 });
+        simulation.connect(cable_2, both).unwrap();
+        simulation.connect(channels[1], both).unwrap();
+        let either = simulation.add("or", Component::Or { delay: 1.0 });
+        simulation.connect(cable_2, either).unwrap();
+        simulation.connect(channels[1], either).unwrap();
+
+        let timeline = simulation.run(0.0);
+        assert_eq!(timeline.time(scope_1), Some(112.0));
+        assert_eq!(timeline.time(cable_2), Some(117.0));
+        assert_eq!(timeline.time(both), Some(161.0));
+        assert_eq!(timeline.time(either), Some(118.0));
+        assert!(timeline.issues.is_empty());
+
+        let mut event_graph = DelayGraph::new();
+        timeline.add_to_graph(&mut event_graph, 0, &[(scope_1, 1), (both, 2)]).unwrap();
+        assert_eq!(event_graph.get_delay(0, 1, 0, 2), Some(49.0));
+
+        // running again after a change moves the times already written
+        simulation.devices[both].component = Component::And { delay: 11.0 };
+        let timeline = simulation.run(0.0);
+        timeline.add_to_graph(&mut event_graph, 0, &[(scope_1, 1), (both, 2)]).unwrap();
+        assert_eq!(event_graph.get_delay(0, 2, 0, 1), Some(-59.0));
+    }
+
+    #[test]
+    /// master -> cable 10 -> channel 100 -> cable 20, entered from the far end
+    fn built_from_the_graph() {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_delay(1, 0, 1, 1, 10.0).unwrap();
+        event_graph.add_channel(1, 1, 2, 1, 0.0, 1000.0).unwrap();
+        event_graph.update_delay(1, 1, 2, 1, 100.0).unwrap();
+        event_graph.add_delay(3, 1, 2, 1, -20.0).unwrap();
+        event_graph.add_time(3, 1, 0.0).unwrap();
+        assert!(Simulation::from_graph(&event_graph, 4, 0).is_err());
+
+        let (simulation, devices) = Simulation::from_graph(&event_graph, 1, 0).unwrap();
+        assert_eq!(devices.len(), 4);
+        let device = |timebase, event| devices[&TimebaseEventKey::new(timebase, event)];
+        assert_eq!(simulation.devices[device(2, 1)].component, Component::DelayChannel { delay: 100.0 });
+        assert_eq!(simulation.devices[device(3, 1)].component, Component::Cable { delay: 20.0 });
+        let timeline = simulation.run(0.0);
+        assert_eq!(timeline.time(device(3, 1)), Some(130.0));
+        assert!(timeline.issues.is_empty());
+    }
+
+    #[test]
+    /// master -> cable -5 -> or, which the master also reaches through cable 1
+    fn negative_delay_stops_the_pulse() {
+        let mut simulation = Simulation::new();
+        let master = simulation.add("master", Component::Master);
+        let backwards = simulation.add("backwards cable", Component::Cable { delay: -5.0 });
+        let forwards = simulation.add("cable", Component::Cable { delay: 1.0 });
+        let either = simulation.add("or", Component::Or { delay: 0.0 });
+        simulation.connect(master, backwards).unwrap();
+        simulation.connect(master, forwards).unwrap();
+        simulation.connect(backwards, either).unwrap();
+        simulation.connect(forwards, either).unwrap();
+
+        let timeline = simulation.run(0.0);
+        assert_eq!(timeline.time(backwards), Some(-5.0));
+        assert_eq!(timeline.time(either), Some(1.0));
+        assert_eq!(timeline.issues, vec![Issue::FiresBeforeInput {device: backwards, input_time: 0.0, fire_time: -5.0}]);
+    }
+
+    #[test]
+    fn causality_and_unconnected_devices() {
+        let mut simulation = Simulation::new();
+        let master = simulation.add("master", Component::Master);
+        let backwards = simulation.add("backwards cable", Component::Cable { delay: -5.0 });
+        simulation.connect(master, backwards).unwrap();
+        let lonely = simulation.add("unplugged scope", Component::Detector { delay: 0.0 });
+        let both = simulation.add("and", Component::And { delay: 0.0 });
+        simulation.connect(backwards, both).unwrap();
+        simulation.connect(lonely, both).unwrap();
+
+        let timeline = simulation.run(0.0);
+        assert_eq!(timeline.issues, vec![
+            Issue::FiresBeforeInput {device: backwards, input_time: 0.0, fire_time: -5.0},
+            Issue::NeverFires {device: lonely},
+            Issue::NeverFires {device: both},
+        ]);
+    }
+}
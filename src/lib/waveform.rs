@@ -0,0 +1,279 @@
+use std::collections::BTreeMap;
+
+use crate::{DelayGraph, Errors, Event, TimebaseEventKey};
+
+/// Sample times of a recording, either evenly spaced or listed one by one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimeAxis {
+    Uniform { t0: f64, dt: f64, len: usize },
+    Explicit(Vec<f64>),
+}
+
+impl TimeAxis {
+    pub fn len(&self) -> usize {
+        match self {
+            TimeAxis::Uniform { len, .. } => *len,
+            TimeAxis::Explicit(times) => times.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn times(&self) -> Vec<f64> {
+        match self {
+            TimeAxis::Uniform { t0, dt, len } => (0..*len).map(|i| t0 + i as f64 * dt).collect(),
+            TimeAxis::Explicit(times) => times.clone(),
+        }
+    }
+
+    pub fn first(&self) -> Option<f64> {
+        match self {
+            TimeAxis::Uniform { t0, len, .. } => (*len > 0).then_some(*t0),
+            TimeAxis::Explicit(times) => times.first().copied(),
+        }
+    }
+
+    pub fn last(&self) -> Option<f64> {
+        match self {
+            TimeAxis::Uniform { t0, dt, len } => (*len > 0).then(|| t0 + (*len - 1) as f64 * dt),
+            TimeAxis::Explicit(times) => times.last().copied(),
+        }
+    }
+
+    /// Whether every time is finite and later than the one before, as interpolation needs.
+    pub fn is_increasing(&self) -> bool {
+        match self {
+            TimeAxis::Uniform { t0, dt, len } => *len == 0 || (t0.is_finite() && (*len == 1 || *dt > 0.0) && self.last().unwrap().is_finite()),
+            TimeAxis::Explicit(times) => times.iter().all(|t| t.is_finite()) && times.windows(2).all(|pair| pair[0] < pair[1]),
+        }
+    }
+
+    fn shifted(&self, offset: f64) -> Self {
+        match self {
+            TimeAxis::Uniform { t0, dt, len } => TimeAxis::Uniform { t0: t0 + offset, dt: *dt, len: *len },
+            TimeAxis::Explicit(times) => TimeAxis::Explicit(times.iter().map(|t| t + offset).collect()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub name: String,
+    pub values: Vec<f64>,
+}
+
+/// One change of timebase applied to a waveform, kept so exports can say how the data was moved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resync {
+    pub from: usize,
+    pub to: usize,
+    /// Event the two timebases were aligned on, `Event::T0` to only account for the clock offset.
+    pub event: Event<usize>,
+    pub offset: f64,
+    /// Nodes of the graph the offset was derived through.
+    pub path: Vec<TimebaseEventKey>,
+}
+
+/// Channels recorded together on one timebase.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waveform {
+    pub timebase: usize,
+    pub time: TimeAxis,
+    pub channels: Vec<Channel>,
+    /// Settings read from the file the waveform came from.
+    pub metadata: BTreeMap<String, String>,
+    pub history: Vec<Resync>,
+}
+
+impl Waveform {
+    /// Waveform sampled at `time`, which must be finite and strictly increasing.
+    pub fn new(timebase: usize, time: TimeAxis, channels: Vec<Channel>) -> Result<Self, Errors> {
+        if !time.is_increasing() {
+            return Err(Errors::InvalidBound)
+        }
+        if channels.iter().any(|channel| channel.values.len() != time.len()) {
+            return Err(Errors::LengthMismatch)
+        }
+        Ok(Self {timebase, time, channels, metadata: BTreeMap::new(), history: Vec::new()})
+    }
+
+    /// Multiply every sample time by `factor`, e.g. `1e9` to go from seconds to nanoseconds.
+    pub fn scale_time(mut self, factor: f64) -> Self {
+        self.time = match self.time {
+            TimeAxis::Uniform { t0, dt, len } => TimeAxis::Uniform { t0: t0 * factor, dt: dt * factor, len },
+            TimeAxis::Explicit(times) => TimeAxis::Explicit(times.iter().map(|t| t * factor).collect()),
+        };
+        self
+    }
+
+    pub fn channel(&self, name: &str) -> Option<&Channel> {
+        self.channels.iter().find(|channel| channel.name == name)
+    }
+
+    /// Re-express the waveform on `timebase`.
+    ///
+    /// With `Event::T0` only the offset between the two clocks is applied, so each sample
+    /// keeps the moment it arrived at the detector. With an event, the event is lined up
+    /// with its time on the target timebase, which also removes the information delay
+    /// of the signal path the event was seen through.
+    pub fn resync(&self, graph: &DelayGraph, timebase: usize, event: Event<usize>) -> Result<Waveform, Errors> {
+        let (offset, path) = match event {
+            Event::T0 => {
+                let path = graph.path(self.timebase, Event::T0, timebase, Event::T0).ok_or(Errors::NotFound)?;
+                // a later origin on the target timebase makes every time on it smaller
+                (-graph.path_delay(&path), path)
+            },
+            Event::Event(event) => {
+                let source_time = graph.get_time(self.timebase, event).ok_or(Errors::NotFound)?;
+                let target_time = graph.get_time(timebase, event).ok_or(Errors::NotFound)?;
+                let path = graph.path(self.timebase, Event::Event(event), timebase, Event::Event(event)).unwrap_or_default();
+                (target_time - source_time, path)
+            },
+        };
+        let mut history = self.history.clone();
+        history.push(Resync {from: self.timebase, to: timebase, event, offset, path});
+        Ok(Waveform {
+            timebase,
+            time: self.time.shifted(offset),
+            channels: self.channels.clone(),
+            metadata: self.metadata.clone(),
+            history,
+        })
+    }
+
+    /// Linearly interpolate every channel onto `time`, with NaN outside the recording.
+    pub fn resample(&self, time: &TimeAxis) -> Waveform {
+        let source_times = self.time.times();
+        let target_times = time.times();
+        let channels = self.channels.iter().map(|channel| Channel {
+            name: channel.name.clone(),
+            values: target_times.iter().map(|&t| interpolate(&source_times, &channel.values, t)).collect(),
+        }).collect();
+        Waveform {
+            timebase: self.timebase,
+            time: time.clone(),
+            channels,
+            metadata: self.metadata.clone(),
+            history: self.history.clone(),
+        }
+    }
+}
+
+/// Most points a resampling grid may have, so a tiny `dt` can't exhaust memory.
+const MAX_GRID_LEN: usize = 1 << 27;
+
+/// Uniform grid with spacing `dt` from `start` to `end`, `None` unless `dt` is positive,
+/// `start` is no later than `end`, all are finite and the grid is not unreasonably long.
+pub(crate) fn grid(start: f64, end: f64, dt: f64) -> Option<TimeAxis> {
+    if !(start.is_finite() && end.is_finite() && dt.is_finite() && dt > 0.0 && start <= end) {
+        return None
+    }
+    let steps = ((end - start) / dt).floor();
+    if steps >= MAX_GRID_LEN as f64 {
+        return None
+    }
+    Some(TimeAxis::Uniform {t0: start, dt, len: steps as usize + 1})
+}
+
+/// Uniform grid with spacing `dt` covering every waveform, for resampling them together.
+pub fn shared_grid(waveforms: &[Waveform], dt: f64) -> Option<TimeAxis> {
+    let start = waveforms.iter().filter_map(|waveform| waveform.time.first()).reduce(f64::min)?;
+    let end = waveforms.iter().filter_map(|waveform| waveform.time.last()).reduce(f64::max)?;
+    grid(start, end, dt)
+}
+
+/// Value at `t` from samples at increasing `times`, NaN outside them.
+pub(crate) fn interpolate(times: &[f64], values: &[f64], t: f64) -> f64 {
+    let upper = times.partition_point(|&time| time < t);
+    if upper == times.len() {
+        return if times.last() == Some(&t) { values[upper - 1] } else { f64::NAN }
+    }
+    if times[upper] == t {
+        return values[upper]
+    }
+    if upper == 0 {
+        return f64::NAN
+    }
+    let (t_1, t_2) = (times[upper - 1], times[upper]);
+    let (v_1, v_2) = (values[upper - 1], values[upper]);
+    v_1 + (t - t_1) / (t_2 - t_1) * (v_2 - v_1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// experiment 1 and scope 2, current start is event 1
+    fn graph() -> DelayGraph {
+        let mut event_graph = DelayGraph::new();
+        event_graph.add_time(1, 1, 0.0).unwrap();
+        event_graph.add_time(2, 1, 2500.0).unwrap();
+        event_graph.add_delay(1, 1, 2, 1, 65.5).unwrap();
+        event_graph
+    }
+
+    fn ramp() -> Waveform {
+        let time = TimeAxis::Uniform {t0: 2490.0, dt: 5.0, len: 5};
+        let channels = vec![Channel {name: "current".to_owned(), values: vec![0.0, 0.0, 1.0, 2.0, 3.0]}];
+        Waveform::new(2, time, channels).unwrap()
+    }
+
+    #[test]
+    fn resync_on_event_and_clock() {
+        let graph = graph();
+        let on_event = ramp().resync(&graph, 1, Event::Event(1)).unwrap();
+        assert_eq!(on_event.time, TimeAxis::Uniform {t0: -10.0, dt: 5.0, len: 5});
+        assert_eq!(on_event.history[0].offset, -2500.0);
+
+        // the clock offset leaves the cable delay in
+        let on_clock = ramp().resync(&graph, 1, Event::T0).unwrap();
+        assert_eq!(on_clock.time.first(), Some(55.5));
+        assert_eq!(on_clock.history[0].path.first(), Some(&TimebaseEventKey::new_t0(2)));
+        assert!(ramp().resync(&graph, 3, Event::T0).is_err());
+    }
+
+    #[test]
+    fn resample_onto_shared_grid() {
+        let waveform = ramp();
+        let other = Waveform::new(2, TimeAxis::Explicit(vec![2500.0, 2512.5]), vec![]).unwrap();
+        let grid = shared_grid(&[waveform.clone(), other], 2.5).unwrap();
+        assert_eq!(grid, TimeAxis::Uniform {t0: 2490.0, dt: 2.5, len: 10});
+
+        let resampled = waveform.resample(&grid);
+        let values = &resampled.channels[0].values;
+        assert_eq!(&values[..9], &[0.0, 0.0, 0.0, 0.5, 1.0, 1.5, 2.0, 2.5, 3.0]);
+        assert!(values[9].is_nan());
+
+        let beyond = waveform.resample(&TimeAxis::Explicit(vec![2480.0, 2520.0]));
+        assert!(beyond.channels[0].values.iter().all(|value| value.is_nan()));
+    }
+
+    #[test]
+    fn invalid_grids() {
+        let waveforms = [ramp()];
+        for dt in [0.0, -2.5, f64::NAN, f64::INFINITY, 1e-300] {
+            assert!(shared_grid(&waveforms, dt).is_none());
+        }
+    }
+
+    #[test]
+    fn times_must_increase() {
+        for dt in [-5.0, 0.0, f64::NAN, f64::INFINITY] {
+            let time = TimeAxis::Uniform {t0: 2490.0, dt, len: 5};
+            assert!(matches!(Waveform::new(2, time, vec![]), Err(Errors::InvalidBound)));
+        }
+        for times in [vec![0.0, f64::INFINITY], vec![0.0, 2.0, 1.0], vec![1.0, 1.0], vec![f64::NAN]] {
+            assert!(matches!(Waveform::new(2, TimeAxis::Explicit(times), vec![]), Err(Errors::InvalidBound)));
+        }
+        assert!(Waveform::new(2, TimeAxis::Uniform {t0: 0.0, dt: 0.0, len: 1}, vec![]).is_ok());
+        assert!(Waveform::new(2, TimeAxis::Explicit(vec![]), vec![]).is_ok());
+    }
+
+    #[test]
+    fn channel_lengths_must_match() {
+        let channels = vec![Channel {name: "short".to_owned(), values: vec![0.0]}];
+        assert!(Waveform::new(1, TimeAxis::Explicit(vec![0.0, 1.0]), channels).is_err());
+    }
+}